@@ -0,0 +1,683 @@
+//! The HTTP API, independent of how requests arrive: the flows.network webhook handler and the
+//! native server both turn their requests into an `ApiRequest` and hand it to `route`.
+
+use crate::ask::{ ask, ChatError };
+use crate::credentials::GithubCredentials;
+use crate::embeddings::EmbeddingBackend;
+use crate::github_data_fetchers::{
+    get_repo,
+    search_discussions,
+    search_issue,
+    search_repository,
+    search_users,
+    summarize_discussions,
+    MemoryType,
+    RepoAccessError,
+    ReportWindow,
+};
+use crate::formatting::{ format_issues, format_repos, format_users };
+use crate::ingest::ingest_since_checkpoint;
+use crate::jobs::{ load_job, ReportJob };
+use crate::llm::{ LlmConfig, LlmSession };
+use crate::memory::{ query_memories, NothingStored };
+use crate::memory_search::search_memories;
+use crate::reports::{
+    org_report,
+    release_notes,
+    weekly_report,
+    weekly_report_from_memory,
+    DryRunPlan,
+    Report,
+    ReportTarget,
+};
+use crate::schedules::{
+    delete_schedule,
+    load_schedules,
+    run_due_schedules,
+    save_schedule,
+    schedule_state,
+    ReportSchedule,
+};
+use crate::sections::{ sections_preset, ReportSection };
+use chrono::{ DateTime, Duration, Utc };
+use serde::Serialize;
+use serde_json::{ json, Value };
+use std::collections::HashMap;
+
+pub struct ApiRequest {
+    /// `GET`, `POST` and so on.
+    pub method: String,
+    /// Path below the endpoint, such as `/report/weekly`.
+    pub path: String,
+    pub query: HashMap<String, Value>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+pub struct ApiResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// A report to run once the response has been sent.
+    pub background: Option<ReportJob>,
+}
+
+impl ApiResponse {
+    fn new(status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        ApiResponse {
+            status,
+            headers: vec![(String::from("content-type"), content_type.to_string())],
+            body,
+            background: None,
+        }
+    }
+
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => ApiResponse::new(status, "application/json", body),
+            Err(_e) => ApiError::new(500, &format!("Failed to serialize response: {_e}")).into(),
+        }
+    }
+
+    pub fn text(status: u16, content_type: &str, text: String) -> Self {
+        ApiResponse::new(status, content_type, text.into_bytes())
+    }
+}
+
+/// Every failed request gets the body `{"error": {"status": ..., "message": "..."}}`.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: u16, message: &str) -> Self {
+        ApiError {
+            status,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn bad_request(message: &str) -> Self {
+        ApiError::new(400, message)
+    }
+}
+
+impl From<ApiError> for ApiResponse {
+    fn from(error: ApiError) -> Self {
+        ApiResponse::new(
+            error.status,
+            "application/json",
+            json!({"error": {"status": error.status, "message": error.message}})
+                .to_string()
+                .into_bytes()
+        )
+    }
+}
+
+pub async fn route(req: ApiRequest) -> ApiResponse {
+    let path = req.path.trim_matches('/').to_string();
+    let result = match path.as_str() {
+        "health" => Ok(health()),
+        // the bare endpoint keeps serving the weekly report, as it always has
+        "" | "report/weekly" => weekly_route(&req, false).await,
+        "report/user" => weekly_route(&req, true).await,
+        "report/org" => org_route(&req).await,
+        "release-notes" => release_notes_route(&req).await,
+        // starting a job isn't safe to repeat, so a link preview or crawler mustn't set one off
+        "report" if req.method != "POST" => {
+            Err(ApiError::new(405, "Start a report job with POST /report"))
+        }
+        "report" => start_job_route(&req).await,
+        "ingest" => ingest_route(&req).await,
+        "memories" => memories_route(&req).await,
+        "ask" => ask_route(&req).await,
+        "search/issues" => search_route(&req, SearchKind::Issues).await,
+        "search/repos" => search_route(&req, SearchKind::Repos).await,
+        "search/users" => search_route(&req, SearchKind::Users).await,
+        "search/discussions" => search_route(&req, SearchKind::Discussions).await,
+        "search/memories" => search_memories_route(&req).await,
+        "schedules" => schedules_route(&req),
+        "schedules/save" => save_schedule_route(&req),
+        "schedules/delete" => delete_schedule_route(&req),
+        "schedules/run" => run_schedules_route(&req).await,
+        _ if path.starts_with("report/") => job_route(&req, &path["report/".len()..]),
+        _ => Err(ApiError::new(404, &format!("No such route: /{path}"))),
+    };
+
+    match result {
+        Ok(response) => response,
+        Err(error) => error.into(),
+    }
+}
+
+fn health() -> ApiResponse {
+    ApiResponse::json(200, &json!({"status": "ok", "version": env!("CARGO_PKG_VERSION")}))
+}
+
+fn query_str<'a>(req: &'a ApiRequest, key: &str) -> Option<&'a str> {
+    req.query
+        .get(key)
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+}
+
+fn required_str<'a>(req: &'a ApiRequest, key: &str) -> Result<&'a str, ApiError> {
+    query_str(req, key).ok_or_else(|| ApiError::bad_request(&format!("Missing parameter: {key}")))
+}
+
+fn query_number<T: std::str::FromStr>(req: &ApiRequest, key: &str, default: T) -> Result<T, ApiError> {
+    match query_str(req, key) {
+        None => Ok(default),
+        Some(raw) =>
+            raw
+                .parse::<T>()
+                .map_err(|_e| ApiError::bad_request(&format!("Invalid value for {key}: {raw}"))),
+    }
+}
+
+/// The caller's GitHub token comes from the `Authorization` or `X-GitHub-Token` header only. A
+/// `token` query parameter is refused rather than ignored, since it would end up in access logs.
+fn credentials(req: &ApiRequest) -> Result<GithubCredentials, ApiError> {
+    match req.query.contains_key("token") {
+        true =>
+            Err(
+                ApiError::bad_request(
+                    "The token query parameter is no longer accepted; send it as 'Authorization: Bearer <token>' instead."
+                )
+            ),
+        false => Ok(GithubCredentials::from_headers(&req.headers)),
+    }
+}
+
+/// A `{"sections": [...]}` body wins over the `sections` preset name.
+fn sections(req: &ApiRequest) -> Result<Vec<ReportSection>, ApiError> {
+    let custom = serde_json
+        ::from_slice::<Value>(&req.body)
+        .ok()
+        .and_then(|v| v.get("sections").cloned());
+    match custom {
+        Some(raw) =>
+            match serde_json::from_value::<Vec<ReportSection>>(raw) {
+                Ok(custom) if !custom.is_empty() => Ok(custom),
+                _ =>
+                    Err(
+                        ApiError::bad_request(
+                            "'sections' must be a non-empty array of {key, instruction, required} objects."
+                        )
+                    ),
+            }
+        None => {
+            let preset = query_str(req, "sections").unwrap_or("default");
+            sections_preset(preset).ok_or_else(||
+                ApiError::bad_request(&format!("Unknown sections preset: {preset}"))
+            )
+        }
+    }
+}
+
+/// For routes whose arguments were already checked: a repo that is missing, hidden or refused,
+/// or has nothing stored, answers 404 or 403, and any other failure came from GitHub or the LLM
+/// and answers 502.
+fn upstream_error(error: anyhow::Error) -> ApiError {
+    if let Some(access) = error.downcast_ref::<RepoAccessError>() {
+        return ApiError::new(access.status(), &access.to_string());
+    }
+    match error.downcast_ref::<NothingStored>() {
+        Some(nothing) => ApiError::new(404, &nothing.to_string()),
+        None => ApiError::new(502, &error.to_string()),
+    }
+}
+
+fn report_response(report: &Report, format: Option<&str>, dry_run: bool) -> ApiResponse {
+    match format {
+        _ if dry_run => ApiResponse::json(200, &report.metadata),
+        Some("json") => ApiResponse::json(200, report),
+        Some("markdown") => ApiResponse::text(200, "text/markdown", report.to_markdown()),
+        _ => ApiResponse::text(200, "text/plain", report.body.clone()),
+    }
+}
+
+/// `source=memory` builds a repo report from the summaries the daily ingest stored.
+fn from_memory(req: &ApiRequest) -> bool {
+    query_str(req, "source") == Some("memory")
+}
+
+async fn weekly_route(req: &ApiRequest, user_required: bool) -> Result<ApiResponse, ApiError> {
+    let owner_repo = query_str(req, "owner_repo").ok_or_else(||
+        ApiError::bad_request("You must provide an owner and repo name.")
+    )?;
+    let user_name = match user_required {
+        true => Some(required_str(req, "username")?.to_string()),
+        false => query_str(req, "username").map(String::from),
+    };
+    let window = ReportWindow::last_days(query_number(req, "n_days", 7u16)?);
+    let sections = sections(req)?;
+    let creds = credentials(req)?;
+
+    if from_memory(req) {
+        require_repo_access(req, owner_repo).await?;
+    }
+
+    let llm = LlmSession::new(LlmConfig::load(&req.query));
+    let report = match from_memory(req) {
+        true => weekly_report_from_memory(owner_repo, user_name, &window, &sections, &llm).await,
+        false => weekly_report(owner_repo, user_name, &window, &creds, &sections, &llm).await,
+    }.map_err(upstream_error)?;
+
+    Ok(report_response(&report, query_str(req, "format"), llm.is_dry_run()))
+}
+
+async fn org_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let org = required_str(req, "org")?;
+    let window = ReportWindow::last_days(query_number(req, "n_days", 7u16)?);
+    let max_repos = query_number(req, "max_repos", 5usize)?;
+    let sections = sections(req)?;
+    let creds = credentials(req)?;
+
+    let llm = LlmSession::new(LlmConfig::load(&req.query));
+    let report = org_report(org, &window, max_repos, &creds, &sections, &llm).await
+        .map_err(upstream_error)?;
+
+    Ok(match query_str(req, "format") {
+        Some("json") => ApiResponse::json(200, &report),
+        Some("markdown") => ApiResponse::text(200, "text/markdown", report.to_markdown()),
+        _ => ApiResponse::text(200, "text/plain", report.to_text()),
+    })
+}
+
+async fn release_notes_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let owner_repo = required_str(req, "owner_repo")?;
+    let window = ReportWindow::last_days(query_number(req, "n_days", 7u16)?);
+    let creds = credentials(req)?;
+
+    let llm = LlmSession::new(LlmConfig::load(&req.query));
+    let notes = release_notes(owner_repo, &window, &creds, &llm).await
+        .map_err(upstream_error)?;
+
+    Ok(match query_str(req, "format") {
+        _ if llm.is_dry_run() => ApiResponse::json(200, &notes.metadata),
+        Some("json") => ApiResponse::json(200, &notes),
+        _ => ApiResponse::text(200, "text/markdown", notes.body),
+    })
+}
+
+/// Starts a report as a background job and answers 202 with its id right away. `kind` is `repo`
+/// (the default), `org` or `release_notes`; the other parameters are those of the matching
+/// route.
+async fn start_job_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let target = match query_str(req, "kind").unwrap_or("repo") {
+        "repo" =>
+            ReportTarget::Repo {
+                owner_repo: required_str(req, "owner_repo")?.to_string(),
+                username: query_str(req, "username").map(String::from),
+                from_memory: from_memory(req),
+            },
+        "org" =>
+            ReportTarget::Org {
+                org: required_str(req, "org")?.to_string(),
+                max_repos: query_number(req, "max_repos", 5usize)?,
+            },
+        "release_notes" =>
+            ReportTarget::ReleaseNotes {
+                owner_repo: required_str(req, "owner_repo")?.to_string(),
+            },
+        other => {
+            return Err(ApiError::bad_request(&format!("Unknown report kind: {other}")));
+        }
+    };
+    if let ReportTarget::Repo { owner_repo, from_memory: true, .. } = &target {
+        require_repo_access(req, owner_repo).await?;
+    }
+    let window = ReportWindow::last_days(query_number(req, "n_days", 7u16)?);
+    let sections = sections(req)?;
+    let creds = credentials(req)?;
+
+    let job = ReportJob::queue(target, window, creds, sections, req.query.clone());
+    let id = job.record().id.clone();
+    let mut response = ApiResponse::json(
+        202,
+        &json!({"id": id, "status": job.record().status, "poll": format!("/report/{id}")})
+    );
+    response.background = Some(job);
+    Ok(response)
+}
+
+/// Fetches and summarizes what happened in `owner_repo` since its last ingest, `first_days`
+/// (default 1) back for the first one, and stores the summaries for `source=memory` reports.
+async fn ingest_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let owner_repo = required_str(req, "owner_repo")?;
+    let first_days = query_number(req, "first_days", 1u16)?;
+    let creds = credentials(req)?;
+
+    let llm = LlmSession::new(LlmConfig::load(&req.query));
+    let run = ingest_since_checkpoint(owner_repo, first_days, chrono::Utc::now(), &creds, &llm).await
+        .map_err(upstream_error)?;
+
+    Ok(ApiResponse::json(200, &json!({"ingest": run, "usage": llm.usage()})))
+}
+
+/// `since` and `until` (RFC 3339), or else the `n_days` up to now.
+fn memory_window(req: &ApiRequest, default_days: u16) -> Result<ReportWindow, ApiError> {
+    let timestamp = |key: &str| -> Result<Option<DateTime<Utc>>, ApiError> {
+        match query_str(req, key) {
+            None => Ok(None),
+            Some(raw) =>
+                DateTime::parse_from_rfc3339(raw)
+                    .map(|at| Some(at.with_timezone(&Utc)))
+                    .map_err(|_e| ApiError::bad_request(&format!("Invalid value for {key}: {raw}"))),
+        }
+    };
+    let until = timestamp("until")?.unwrap_or_else(Utc::now);
+    let since = match timestamp("since")? {
+        Some(since) => since,
+        None => until - Duration::days(query_number(req, "n_days", default_days)? as i64),
+    };
+    Ok(ReportWindow::between(since, until))
+}
+
+fn memory_type(req: &ApiRequest) -> Result<Option<MemoryType>, ApiError> {
+    match query_str(req, "type") {
+        Some(name) =>
+            MemoryType::from_name(name)
+                .map(Some)
+                .ok_or_else(|| ApiError::bad_request(&format!("Unknown memory type: {name}"))),
+        None => Ok(None),
+    }
+}
+
+/// Stored memories may have been summarized with another caller's token, so they are only
+/// served to callers who can see the repo themselves.
+async fn require_repo_access(req: &ApiRequest, owner_repo: &str) -> Result<(), ApiError> {
+    let creds = credentials(req)?.for_owner(owner_repo.split('/').next().unwrap_or(owner_repo));
+    get_repo(owner_repo, &creds).await.map_err(upstream_error)?;
+    Ok(())
+}
+
+/// Stored summaries of `owner_repo`, newest first: `since` and `until` (RFC 3339) or `n_days`
+/// (default 7) pick the period, `username` and `type` (`commit`, `issue` or `discussion`) narrow
+/// it down.
+async fn memories_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let owner_repo = required_str(req, "owner_repo")?;
+    let window = memory_window(req, 7)?;
+    let memory_type = memory_type(req)?;
+    require_repo_access(req, owner_repo).await?;
+
+    let memories = query_memories(owner_repo, &window, query_str(req, "username"), memory_type);
+
+    Ok(
+        ApiResponse::json(
+            200,
+            &json!({"owner_repo": owner_repo, "window": window, "memories": memories})
+        )
+    )
+}
+
+/// The stored summaries of `owner_repo` closest in meaning to the question in `q`, with their
+/// scores. Takes the period and filters of `/memories`, over the last 90 days by default, and
+/// `limit` (1-100, default 10).
+async fn search_memories_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let owner_repo = required_str(req, "owner_repo")?;
+    let q = required_str(req, "q")?;
+    let window = memory_window(req, 90)?;
+    let memory_type = memory_type(req)?;
+    let limit = query_number(req, "limit", 10usize)?.clamp(1, 100);
+    require_repo_access(req, owner_repo).await?;
+
+    let backend = EmbeddingBackend::from_env().map_err(|_e| ApiError::new(500, &_e.to_string()))?;
+    let hits = search_memories(
+        owner_repo,
+        q,
+        &window,
+        query_str(req, "username"),
+        memory_type,
+        limit,
+        &backend
+    ).await.map_err(|_e| ApiError::new(502, &format!("Embedding failed: {_e}")))?;
+
+    Ok(ApiResponse::json(200, &json!({"query": q, "window": window, "results": hits})))
+}
+
+/// Answers `question` about `owner_repo` from its stored summaries, over the last 30 days by
+/// default; pass the returned `chat_id` back to ask a follow-up.
+async fn ask_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let owner_repo = required_str(req, "owner_repo")?;
+    let question = required_str(req, "question")?;
+    let window = memory_window(req, 30)?;
+    require_repo_access(req, owner_repo).await?;
+
+    let llm = LlmSession::new(LlmConfig::load(&req.query));
+    let answer = ask(
+        owner_repo,
+        question,
+        query_str(req, "chat_id"),
+        &window,
+        query_str(req, "username"),
+        &llm
+    ).await.map_err(|_e| match _e.downcast_ref::<ChatError>() {
+        Some(chat) => ApiError::new(chat.status(), &chat.to_string()),
+        None => upstream_error(_e),
+    })?;
+
+    Ok(match llm.is_dry_run() {
+        true => ApiResponse::json(200, &DryRunPlan::new(Vec::new(), llm.planned_calls())),
+        false => ApiResponse::json(200, &answer),
+    })
+}
+
+/// The job's status and progress; once it is done, `format` (`text`, `markdown` or `json`)
+/// answers with the report itself instead.
+fn job_route(req: &ApiRequest, id: &str) -> Result<ApiResponse, ApiError> {
+    let job = load_job(id).ok_or_else(|| ApiError::new(404, &format!("No report job {id}")))?;
+    match (&job.result, query_str(req, "format")) {
+        (Some(result), Some("json")) => Ok(ApiResponse::json(200, &result.json)),
+        (Some(result), Some("markdown")) =>
+            Ok(ApiResponse::text(200, "text/markdown", result.markdown.clone())),
+        (Some(result), Some(_)) => Ok(ApiResponse::text(200, "text/plain", result.text.clone())),
+        _ => Ok(ApiResponse::json(200, &job)),
+    }
+}
+
+enum SearchKind {
+    Issues,
+    Repos,
+    Users,
+    Discussions,
+}
+
+/// The account a search is confined to by a `repo:`, `org:` or `user:` qualifier, if any.
+fn search_owner(q: &str) -> Option<&str> {
+    q.split_whitespace().find_map(|term| {
+        term.strip_prefix("repo:")
+            .and_then(|repo| repo.split('/').next())
+            .or_else(|| term.strip_prefix("org:"))
+            .or_else(|| term.strip_prefix("user:"))
+    })
+}
+
+/// Takes a raw GitHub search query in `q`; `limit` (1-100, default 30) and `cursor` page through
+/// the results, `cursor` being the `end_cursor` of the previous page.
+async fn search_route(req: &ApiRequest, kind: SearchKind) -> Result<ApiResponse, ApiError> {
+    let q = required_str(req, "q")?;
+    let limit = query_number(req, "limit", 30u16)?;
+    let cursor = query_str(req, "cursor");
+    let creds = match search_owner(q) {
+        Some(owner) => credentials(req)?.for_owner(owner),
+        None => credentials(req)?,
+    };
+    let github_error = |_e: anyhow::Error| ApiError::new(502, &format!("GitHub search failed: {_e}"));
+    // `format=text` gives the flat one-line-per-item rendering instead of JSON
+    let as_text = |text: String| Ok(ApiResponse::text(200, "text/plain", text));
+    let text_format = query_str(req, "format") == Some("text");
+
+    let page = match kind {
+        SearchKind::Issues => {
+            let page = search_issue(q, limit, cursor, &creds).await.map_err(github_error)?;
+            if text_format {
+                return as_text(format_issues(&page.items));
+            }
+            json!(page)
+        }
+        SearchKind::Repos => {
+            let page = search_repository(q, limit, cursor, &creds).await.map_err(github_error)?;
+            if text_format {
+                return as_text(format_repos(&page.items));
+            }
+            json!(page)
+        }
+        SearchKind::Users => {
+            let page = search_users(q, limit, cursor, &creds).await.map_err(github_error)?;
+            if text_format {
+                return as_text(format_users(&page.items));
+            }
+            json!(page)
+        }
+        SearchKind::Discussions => {
+            let page = search_discussions(q, limit, cursor, &creds).await.map_err(github_error)?;
+            // `summarize=true` adds a model summary per discussion, seen from `username` if given
+            match query_str(req, "summarize") {
+                Some("true") => {
+                    let llm = LlmSession::new(LlmConfig::load(&req.query));
+                    let target_person = query_str(req, "username").map(String::from);
+                    let summaries = summarize_discussions(&page.items, &target_person, "", &llm).await;
+                    json!({"page": page, "summaries": summaries, "usage": llm.usage()})
+                }
+                _ => json!(page),
+            }
+        }
+    };
+
+    Ok(ApiResponse::json(200, &json!({"query": q, "results": page})))
+}
+
+/// Routes that manage schedules need an `X-Admin-Token` header matching `ADMIN_TOKEN`; they are
+/// off while `ADMIN_TOKEN` is unset.
+fn require_admin(req: &ApiRequest) -> Result<(), ApiError> {
+    let expected = std::env
+        ::var("ADMIN_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| ApiError::new(403, "Set ADMIN_TOKEN to enable this route."))?;
+    let given = req.headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("x-admin-token"))
+        .map(|(_, value)| value.trim());
+
+    match given == Some(expected.as_str()) {
+        true => Ok(()),
+        false => Err(ApiError::new(401, "Missing or wrong X-Admin-Token header.")),
+    }
+}
+
+fn schedules_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    require_admin(req)?;
+    let schedules = load_schedules().map_err(|_e| ApiError::new(500, &_e.to_string()))?;
+    let listed = schedules
+        .into_iter()
+        .map(|schedule| {
+            let state = schedule_state(&schedule.id);
+            json!({"schedule": schedule, "state": state})
+        })
+        .collect::<Vec<Value>>();
+    Ok(ApiResponse::json(200, &listed))
+}
+
+/// Takes the schedule as the JSON body; an existing schedule with the same id is replaced.
+fn save_schedule_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    require_admin(req)?;
+    let schedule = serde_json
+        ::from_slice::<ReportSchedule>(&req.body)
+        .map_err(|_e| ApiError::bad_request(&format!("Invalid schedule: {_e}")))?;
+    let id = schedule.id.clone();
+    save_schedule(schedule).map_err(|_e| ApiError::bad_request(&_e.to_string()))?;
+    Ok(ApiResponse::json(200, &json!({"saved": id})))
+}
+
+fn delete_schedule_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    require_admin(req)?;
+    let id = required_str(req, "id")?;
+    match delete_schedule(id) {
+        Ok(true) => Ok(ApiResponse::json(200, &json!({"deleted": id}))),
+        Ok(false) => Err(ApiError::new(404, &format!("No schedule with id {id}"))),
+        Err(_e) => Err(ApiError::bad_request(&_e.to_string())),
+    }
+}
+
+async fn run_schedules_route(req: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    require_admin(req)?;
+    let runs = run_due_schedules(chrono::Utc::now()).await.map_err(|_e|
+        ApiError::new(500, &_e.to_string())
+    )?;
+    Ok(ApiResponse::json(200, &runs))
+}
+
+/// Turns `a=1&b=two%20words` into the same shape of map the webhook handler receives.
+pub fn parse_query(raw: &str) -> HashMap<String, Value> {
+    raw.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |s: &str| {
+                urlencoding
+                    ::decode(&s.replace('+', " "))
+                    .map(|d| d.into_owned())
+                    .unwrap_or_else(|_| s.to_string())
+            };
+            (decode(key), Value::String(decode(value)))
+        })
+        .collect()
+}
+
+/// Serves the API over plain HTTP until the process is stopped.
+#[cfg(feature = "native")]
+pub async fn serve(addr: std::net::SocketAddr) -> anyhow::Result<()> {
+    use hyper::service::{ make_service_fn, service_fn };
+    use hyper::{ Body, Request, Response, Server };
+    use std::convert::Infallible;
+
+    let make_service = make_service_fn(|_conn| async {
+        Ok::<_, Infallible>(
+            service_fn(|req: Request<Body>| async move {
+                let (parts, body) = req.into_parts();
+                let body = hyper::body
+                    ::to_bytes(body).await
+                    .map(|b| b.to_vec())
+                    .unwrap_or_default();
+                let headers = parts.headers
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        value
+                            .to_str()
+                            .ok()
+                            .map(|v| (name.to_string(), v.to_string()))
+                    })
+                    .collect::<Vec<(String, String)>>();
+
+                let mut res = route(ApiRequest {
+                    method: parts.method.as_str().to_string(),
+                    path: parts.uri.path().to_string(),
+                    query: parts.uri.query().map(parse_query).unwrap_or_default(),
+                    headers,
+                    body,
+                }).await;
+                if let Some(job) = res.background.take() {
+                    tokio_native::spawn(job.run());
+                }
+
+                let mut builder = Response::builder().status(res.status);
+                for (name, value) in res.headers {
+                    builder = builder.header(name, value);
+                }
+                Ok::<_, Infallible>(
+                    builder.body(Body::from(res.body)).unwrap_or_else(|_| Response::new(Body::empty()))
+                )
+            })
+        )
+    });
+
+    log::info!("Listening on http://{}", addr);
+    Server::try_bind(&addr)?.serve(make_service).await?;
+
+    Ok(())
+}
@@ -0,0 +1,323 @@
+//! Questions about a repo's recent activity, answered from its stored summaries. Each question
+//! retrieves the summaries closest to it, numbers them, and the answer cites them by number; the
+//! cited ones come back with their urls.
+//!
+//! A conversation is kept in the store under `chat:{chat_id}`, so a follow-up that passes the same
+//! `chat_id` gets the earlier questions and answers as context, on either platform. A chat stays
+//! tied to the repo it started on.
+
+use crate::embeddings::EmbeddingBackend;
+use crate::github_data_fetchers::{ GitMemory, MemoryType, ReportWindow };
+use crate::llm::{ LlmSession, Stage };
+use crate::memory::{ project_memory, query_memories };
+use crate::memory_search::search_memories;
+use crate::platform::{ store_get, store_set };
+use crate::prompts::{ render_prompt, PromptName };
+use crate::utils::{ new_id, project_context, squeeze_fit_post_texts };
+use chrono::{ DateTime, Utc };
+use serde::{ Deserialize, Serialize };
+
+// summaries put in front of the model per question
+const SOURCE_LIMIT: usize = 12;
+// earlier turns given as context; older ones are still stored, up to MAX_TURNS
+const HISTORY_TURNS: usize = 5;
+const MAX_TURNS: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerSource {
+    /// The number the answer cites it by.
+    pub n: usize,
+    pub memory_type: MemoryType,
+    pub title: String,
+    pub author: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatTurn {
+    pub question: String,
+    pub answer: String,
+    pub sources: Vec<AnswerSource>,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub chat_id: String,
+    pub owner_repo: String,
+    pub turns: Vec<ChatTurn>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Answer {
+    pub chat_id: String,
+    pub answer: String,
+    /// The summaries the answer cites, in citation order.
+    pub sources: Vec<AnswerSource>,
+}
+
+/// A question that can't be asked as it stands, as opposed to the model or GitHub failing.
+#[derive(Debug)]
+pub enum ChatError {
+    NoChat(String),
+    /// The chat, the repo it is about and the repo asked about.
+    OtherRepo(String, String, String),
+    NothingStored(String),
+}
+
+impl ChatError {
+    pub fn status(&self) -> u16 {
+        match self {
+            ChatError::NoChat(_) | ChatError::NothingStored(_) => 404,
+            ChatError::OtherRepo(_, _, _) => 400,
+        }
+    }
+}
+
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatError::NoChat(chat_id) => write!(f, "No chat {chat_id}"),
+            ChatError::OtherRepo(chat_id, about, asked) =>
+                write!(f, "Chat {chat_id} is about {about}, not {asked}"),
+            ChatError::NothingStored(owner_repo) =>
+                write!(
+                    f,
+                    "No stored activity for {owner_repo} in this period; run an ingest or a report first"
+                ),
+        }
+    }
+}
+
+impl std::error::Error for ChatError {}
+
+fn chat_key(chat_id: &str) -> String {
+    format!("chat:{chat_id}")
+}
+
+pub fn load_conversation(chat_id: &str) -> Option<Conversation> {
+    store_get(&chat_key(chat_id)).and_then(|value|
+        serde_json::from_value::<Conversation>(value).ok()
+    )
+}
+
+fn save_conversation(conversation: &Conversation) {
+    match serde_json::to_value(conversation) {
+        Ok(value) => store_set(&chat_key(&conversation.chat_id), value),
+        Err(_e) => log::error!("Failed to save chat {}: {}", conversation.chat_id, _e),
+    }
+}
+
+/// The summaries closest in meaning to `query`, or the newest ones when there is no embedding
+/// backend to rank them with.
+async fn retrieve(
+    owner_repo: &str,
+    query: &str,
+    window: &ReportWindow,
+    user: Option<&str>
+) -> Vec<GitMemory> {
+    let ranked = match EmbeddingBackend::from_env() {
+        Ok(backend) =>
+            search_memories(owner_repo, query, window, user, None, SOURCE_LIMIT, &backend).await,
+        Err(_e) => Err(_e),
+    };
+    match ranked {
+        Ok(hits) => hits.into_iter().map(|hit| hit.memory).collect(),
+        Err(_e) => {
+            log::error!("Falling back to the newest summaries of {}: {}", owner_repo, _e);
+            query_memories(owner_repo, window, user, None)
+                .into_iter()
+                .filter(|m| m.memory_type != MemoryType::Meta)
+                .take(SOURCE_LIMIT)
+                .collect()
+        }
+    }
+}
+
+fn source_lines(memories: &[GitMemory]) -> String {
+    memories
+        .iter()
+        .enumerate()
+        .map(|(i, m)| {
+            let date = m.date.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default();
+            format!(
+                "[{}] {:?} by {} {}: {} ({}) {}",
+                i + 1,
+                m.memory_type,
+                m.name,
+                date,
+                m.tag_line.trim(),
+                m.source_url,
+                m.payload.trim()
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn history_lines(turns: &[ChatTurn]) -> String {
+    match turns.len() {
+        0 => String::from("nothing yet"),
+        n =>
+            turns[n.saturating_sub(HISTORY_TURNS)..]
+                .iter()
+                .map(|turn| format!("Q: {}\nA: {}", turn.question, turn.answer))
+                .collect::<Vec<String>>()
+                .join("\n"),
+    }
+}
+
+/// The numbers cited as `[2]` or `[1, 3]`, in order of first citation, limited to `1..=max`.
+fn cited_numbers(answer: &str, max: usize) -> Vec<usize> {
+    let mut cited = Vec::<usize>::new();
+    for part in answer.split('[').skip(1) {
+        let inside = match part.split_once(']') {
+            Some((inside, _)) => inside,
+            None => continue,
+        };
+        for n in inside.split(',').filter_map(|n| n.trim().parse::<usize>().ok()) {
+            if (1..=max).contains(&n) && !cited.contains(&n) {
+                cited.push(n);
+            }
+        }
+    }
+    cited
+}
+
+/// Answers `question` about `owner_repo` in `window`, continuing the conversation `chat_id` if
+/// given, or starting a new one. `user` narrows the summaries to one person's.
+pub async fn ask(
+    owner_repo: &str,
+    question: &str,
+    chat_id: Option<&str>,
+    window: &ReportWindow,
+    user: Option<&str>,
+    llm: &LlmSession
+) -> anyhow::Result<Answer> {
+    let mut conversation = match chat_id {
+        Some(id) => {
+            let conversation = load_conversation(id).ok_or_else(||
+                ChatError::NoChat(id.to_string())
+            )?;
+            if !conversation.owner_repo.eq_ignore_ascii_case(owner_repo) {
+                return Err(
+                    ChatError::OtherRepo(
+                        id.to_string(),
+                        conversation.owner_repo,
+                        owner_repo.to_string()
+                    ).into()
+                );
+            }
+            conversation
+        }
+        None =>
+            Conversation {
+                chat_id: new_id("chat"),
+                owner_repo: owner_repo.to_string(),
+                turns: Vec::new(),
+            },
+    };
+
+    // a follow-up such as "and who reviewed it?" only makes sense with the question before it
+    let query = match conversation.turns.last() {
+        Some(last) => format!("{}\n{}", last.question, question),
+        None => question.to_string(),
+    };
+    let memories = retrieve(owner_repo, &query, window, user).await;
+    if memories.is_empty() {
+        return Err(ChatError::NothingStored(owner_repo.to_string()).into());
+    }
+
+    let project_summary = project_memory(owner_repo).map(|m| m.payload).unwrap_or_default();
+    let sources = squeeze_fit_post_texts(&source_lines(&memories), 6_000, 0.8);
+    let history = squeeze_fit_post_texts(&history_lines(&conversation.turns), 1_500, 0.2);
+    let (system_prompt, user_input) = render_prompt(
+        PromptName::Answer,
+        &[
+            ("project_context", &project_context(&project_summary)),
+            ("owner_repo", owner_repo),
+            ("since", &window.since.format("%Y-%m-%d").to_string()),
+            ("until", &window.until.format("%Y-%m-%d").to_string()),
+            ("sources", &sources),
+            ("history", &history),
+            ("question", question),
+        ]
+    );
+
+    let answer = llm.chat(Stage::Answer, owner_repo, &system_prompt, &user_input).await?;
+    let sources = cited_numbers(&answer, memories.len())
+        .into_iter()
+        .map(|n| {
+            let memory = &memories[n - 1];
+            AnswerSource {
+                n,
+                memory_type: memory.memory_type,
+                title: memory.tag_line.clone(),
+                author: memory.name.clone(),
+                url: memory.source_url.clone(),
+                date: memory.date,
+            }
+        })
+        .collect::<Vec<AnswerSource>>();
+
+    if !llm.is_dry_run() {
+        conversation.turns.push(ChatTurn {
+            question: question.to_string(),
+            answer: answer.clone(),
+            sources: sources.clone(),
+            at: Utc::now(),
+        });
+        let excess = conversation.turns.len().saturating_sub(MAX_TURNS);
+        conversation.turns.drain(..excess);
+        save_conversation(&conversation);
+    }
+
+    Ok(Answer {
+        chat_id: conversation.chat_id,
+        answer,
+        sources,
+    })
+}
+
+#[cfg(all(test, feature = "native"))]
+mod tests {
+    use super::*;
+    use crate::llm::LlmConfig;
+    use crate::memory::remember;
+
+    #[test]
+    fn answer_prompt_fits_oversized_sources() {
+        let store = std::env::temp_dir().join(format!("{}.json", new_id("ask-test-store")));
+        std::env::set_var("GH_ANALYZER_STORE", &store);
+        std::env::set_var("EMBEDDING_BACKEND", "local");
+
+        let payload = (0..3_000).map(|i| format!("word{i}")).collect::<Vec<String>>().join(" ");
+        let memories = (0..SOURCE_LIMIT)
+            .map(|i| GitMemory {
+                memory_type: MemoryType::Commit,
+                name: format!("dev{i}"),
+                tag_line: format!("change {i}"),
+                source_url: format!("https://github.com/o/r/commit/{i}"),
+                payload: payload.clone(),
+                date: Some(Utc::now()),
+            })
+            .collect::<Vec<GitMemory>>();
+        remember("o/r", memories, Vec::new(), Utc::now());
+
+        let mut config = LlmConfig::with_defaults();
+        config.dry_run = true;
+        let llm = LlmSession::new(config);
+        let answer = futures::executor::block_on(
+            ask("o/r", "what changed?", None, &ReportWindow::last_days(7), None, &llm)
+        );
+        let _ = std::fs::remove_file(&store);
+
+        assert!(answer.is_ok());
+        let planned = llm.planned_calls();
+        assert_eq!(planned.len(), 1);
+        // 6k of sources and 1.5k of history, plus the prompt itself
+        assert!(planned[0].prompt_tokens < 9_000);
+    }
+}
@@ -0,0 +1,230 @@
+//! Runs the report engine from a terminal or a cron job, or serves the HTTP API:
+//!
+//!     gh-analyzer report --repo owner/name --days 14 --user alice --format markdown
+//!     gh-analyzer serve --addr 127.0.0.1:8080
+//!     gh-analyzer run-schedules
+//!     gh-analyzer ingest --repo owner/name
+//!
+//! GITHUB_TOKEN and OPENAI_API_KEY are read from the environment (or a `.env` file). Options the
+//! command doesn't know itself, such as `--model`, `--budget-usd` or `--dry-run`, are passed on to
+//! the LLM settings under the same names as the webhook's query parameters.
+
+use dotenv::dotenv;
+use github_analyzer_2::api::serve;
+use github_analyzer_2::credentials::GithubCredentials;
+use github_analyzer_2::github_data_fetchers::ReportWindow;
+use github_analyzer_2::ingest::ingest_since_checkpoint;
+use github_analyzer_2::llm::{ LlmConfig, LlmSession };
+use github_analyzer_2::reports::{ weekly_report, weekly_report_from_memory };
+use github_analyzer_2::schedules::run_due_schedules;
+use github_analyzer_2::sections::{ sections_preset, ReportSection };
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+const USAGE: &str =
+    "Usage: gh-analyzer report --repo <owner/name> [--days <n>] [--user <login>]
+                          [--format text|markdown|json] [--sections <preset>|--sections-file <path>]
+                          [--output <path>] [--from-memory] [--model <name>] [--budget-usd <usd>]
+                          [--dry-run] ...
+       gh-analyzer serve [--addr <host:port>]
+       gh-analyzer run-schedules
+       gh-analyzer ingest --repo <owner/name> [--days <n>] [--model <name>] ...";
+
+struct ReportArgs {
+    owner_repo: String,
+    n_days: u16,
+    user_name: Option<String>,
+    format: String,
+    sections: Vec<ReportSection>,
+    output: Option<String>,
+    from_memory: bool,
+    llm_options: HashMap<String, Value>,
+}
+
+fn parse_report_args(args: &[String], default_days: u16) -> anyhow::Result<ReportArgs> {
+    let mut options = HashMap::<String, Value>::new();
+    let mut iter = args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        let name = match arg.strip_prefix("--") {
+            Some(name) => name.replace('-', "_"),
+            None => {
+                return Err(anyhow::anyhow!("Unexpected argument: {arg}"));
+            }
+        };
+        let value = match iter.peek() {
+            Some(next) if !next.starts_with("--") => iter.next().unwrap().to_string(),
+            _ => String::from("true"),
+        };
+        options.insert(name, Value::String(value));
+    }
+
+    let mut take = |key: &str| -> Option<String> {
+        options.remove(key).and_then(|v| v.as_str().map(String::from))
+    };
+
+    let owner_repo = match take("repo") {
+        Some(repo) if repo.split('/').count() == 2 => repo,
+        Some(repo) => {
+            return Err(anyhow::anyhow!("--repo must look like owner/name, got {repo}"));
+        }
+        None => {
+            return Err(anyhow::anyhow!("--repo is required"));
+        }
+    };
+    let n_days = match take("days") {
+        Some(days) => days.parse::<u16>().map_err(|_e| anyhow::anyhow!("Invalid --days: {days}"))?,
+        None => default_days,
+    };
+    let user_name = take("user");
+    let format = take("format").unwrap_or_else(|| String::from("text"));
+    if !["text", "markdown", "json"].contains(&format.as_str()) {
+        return Err(anyhow::anyhow!("Unknown --format: {format}"));
+    }
+    let sections = match (take("sections_file"), take("sections")) {
+        (Some(path), _) => {
+            let raw = std::fs::read_to_string(&path)?;
+            serde_json::from_str::<Vec<ReportSection>>(&raw)?
+        }
+        (None, preset) => {
+            let preset = preset.unwrap_or_else(|| String::from("default"));
+            sections_preset(&preset).ok_or_else(||
+                anyhow::anyhow!("Unknown sections preset: {preset}")
+            )?
+        }
+    };
+    let output = take("output");
+    let from_memory = take("from_memory").as_deref() == Some("true");
+
+    Ok(ReportArgs {
+        owner_repo,
+        n_days,
+        user_name,
+        format,
+        sections,
+        output,
+        from_memory,
+        llm_options: options,
+    })
+}
+
+async fn run_report(args: ReportArgs) -> anyhow::Result<()> {
+    let llm = LlmSession::new(LlmConfig::load(&args.llm_options));
+
+    let window = ReportWindow::last_days(args.n_days);
+    let report = match args.from_memory {
+        true =>
+            weekly_report_from_memory(
+                &args.owner_repo,
+                args.user_name,
+                &window,
+                &args.sections,
+                &llm
+            ).await?,
+        false =>
+            weekly_report(
+                &args.owner_repo,
+                args.user_name,
+                &window,
+                &GithubCredentials::deployment(),
+                &args.sections,
+                &llm
+            ).await?,
+    };
+
+    let rendered = match args.format.as_str() {
+        _ if llm.is_dry_run() => serde_json::to_string_pretty(&report.metadata)?,
+        "json" => serde_json::to_string_pretty(&report)?,
+        "markdown" => report.to_markdown(),
+        _ => report.body,
+    };
+
+    match args.output {
+        Some(path) => std::fs::write(path, rendered)?,
+        None => println!("{rendered}"),
+    }
+
+    Ok(())
+}
+
+/// Runs the schedules that are due and prints what happened to each; meant for cron.
+async fn run_schedules() -> anyhow::Result<()> {
+    let runs = run_due_schedules(chrono::Utc::now()).await?;
+    println!("{}", serde_json::to_string_pretty(&runs)?);
+
+    match runs.iter().any(|run| run.status == "failed") {
+        true => Err(anyhow::anyhow!("Some scheduled reports failed")),
+        false => Ok(()),
+    }
+}
+
+/// Ingests the repo since its checkpoint, `--days` (default 1) back the first time.
+async fn run_ingest(args: ReportArgs) -> anyhow::Result<()> {
+    let llm = LlmSession::new(LlmConfig::load(&args.llm_options));
+    let run = ingest_since_checkpoint(
+        &args.owner_repo,
+        args.n_days,
+        chrono::Utc::now(),
+        &GithubCredentials::deployment(),
+        &llm
+    ).await?;
+    println!("{}", serde_json::to_string_pretty(&run)?);
+    Ok(())
+}
+
+enum Command {
+    Report(ReportArgs),
+    Serve(SocketAddr),
+    RunSchedules,
+    Ingest(ReportArgs),
+}
+
+fn parse_command(args: &[String]) -> anyhow::Result<Command> {
+    match args.split_first() {
+        Some((command, rest)) if command == "report" => Ok(Command::Report(parse_report_args(rest, 7)?)),
+        Some((command, rest)) if command == "serve" => {
+            let addr = match rest {
+                [] => "127.0.0.1:8080",
+                [flag, addr] if flag == "--addr" => addr.as_str(),
+                _ => {
+                    return Err(anyhow::anyhow!("serve only takes --addr <host:port>"));
+                }
+            };
+            Ok(Command::Serve(addr.parse::<SocketAddr>()?))
+        }
+        Some((command, [])) if command == "run-schedules" => Ok(Command::RunSchedules),
+        Some((command, rest)) if command == "ingest" => Ok(Command::Ingest(parse_report_args(rest, 1)?)),
+        _ => Err(anyhow::anyhow!("Expected a command: report, serve, run-schedules or ingest")),
+    }
+}
+
+fn main() {
+    dotenv().ok();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let command = match parse_command(&args) {
+        Ok(command) => command,
+        Err(_e) => {
+            eprintln!("{_e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let runtime = tokio_native::runtime::Builder
+        ::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to start the async runtime");
+
+    let result = match command {
+        Command::Report(report_args) => runtime.block_on(run_report(report_args)),
+        Command::Serve(addr) => runtime.block_on(serve(addr)),
+        Command::RunSchedules => runtime.block_on(run_schedules()),
+        Command::Ingest(ingest_args) => runtime.block_on(run_ingest(ingest_args)),
+    };
+    if let Err(_e) = result {
+        eprintln!("{_e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::github_app::GithubApp;
+use regex::Regex;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// The GitHub identity one report or API request runs under. A caller-supplied token becomes the
+/// `Authorization` header of every GitHub call made for the request, octocrab and raw fetches
+/// alike. Without one, the deployment's own credentials are used: an installation token when a
+/// GitHub App is configured, otherwise the flows.network login for octocrab calls on the platform
+/// and `GITHUB_TOKEN` everywhere else.
+///
+/// The token never goes into a URL, and `Debug` doesn't print it.
+#[derive(Clone, Default)]
+pub struct GithubCredentials {
+    token: Option<String>,
+    owner: Option<String>,
+}
+
+impl GithubCredentials {
+    /// The deployment's own credentials.
+    pub fn deployment() -> Self {
+        GithubCredentials { token: None, owner: None }
+    }
+
+    pub fn with_token(token: &str) -> Self {
+        GithubCredentials {
+            token: Some(token.trim().to_string()).filter(|t| !t.is_empty()),
+            owner: None,
+        }
+    }
+
+    /// Takes the token from `Authorization: Bearer <token>` (or `token <token>`) or from
+    /// `X-GitHub-Token`, falling back to the deployment's credentials when neither is present.
+    pub fn from_headers(headers: &[(String, String)]) -> Self {
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.trim())
+        };
+
+        let from_authorization = header("authorization").and_then(|value| {
+            value
+                .strip_prefix("Bearer ")
+                .or_else(|| value.strip_prefix("bearer "))
+                .or_else(|| value.strip_prefix("token "))
+        });
+
+        match from_authorization.or_else(|| header("x-github-token")) {
+            Some(token) => GithubCredentials::with_token(token),
+            None => GithubCredentials::deployment(),
+        }
+    }
+
+    /// The same credentials for work on `owner`'s repos, which picks the GitHub App installation
+    /// to use. A caller's token is unaffected.
+    pub fn for_owner(&self, owner: &str) -> Self {
+        GithubCredentials {
+            token: self.token.clone(),
+            owner: Some(owner.to_string()),
+        }
+    }
+
+    /// The token supplied by the caller of this request, if any.
+    pub fn caller_token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// The token that replaces the platform's default login: the caller's, or else an App
+    /// installation token. `None` when neither applies.
+    pub async fn scoped_token(&self) -> anyhow::Result<Option<String>> {
+        match (&self.token, GithubApp::configured()) {
+            (Some(token), _) => Ok(Some(token.clone())),
+            (None, Some(app)) => app.installation_token(self.owner.as_deref()).await.map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// The token to send: the caller's, an App installation token, or else `GITHUB_TOKEN`.
+    pub async fn token(&self) -> anyhow::Result<Option<String>> {
+        Ok(
+            self
+                .scoped_token().await?
+                .or_else(|| std::env::var("GITHUB_TOKEN").ok().filter(|t| !t.is_empty()))
+        )
+    }
+
+    pub async fn authorization_header(&self) -> anyhow::Result<Option<String>> {
+        Ok(self.token().await?.map(|token| format!("Bearer {token}")))
+    }
+}
+
+impl fmt::Debug for GithubCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let token = match &self.token {
+            Some(_) => "<caller token, redacted>",
+            None => "<deployment>",
+        };
+        f.debug_struct("GithubCredentials")
+            .field("token", &token)
+            .field("owner", &self.owner)
+            .finish()
+    }
+}
+
+/// Masks anything that looks like a GitHub token or a `token=` query parameter, for text that is
+/// about to be logged.
+pub fn redact_secrets(text: &str) -> String {
+    static PATTERN: OnceLock<Option<Regex>> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(
+            r"(gh[pousr]_[A-Za-z0-9]{20,}|github_pat_[A-Za-z0-9_]{20,}|(?i:token=)[^&\s]+|(?i:bearer )\S+)"
+        ).ok()
+    });
+
+    match pattern {
+        Some(re) => re.replace_all(text, "[REDACTED]").to_string(),
+        None => text.to_string(),
+    }
+}
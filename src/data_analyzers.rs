@@ -1,13 +1,17 @@
+use crate::credentials::GithubCredentials;
 use crate::github_data_fetchers::*;
+use crate::github_host::GithubHost;
+use crate::llm::{ LlmSession, Stage };
+use crate::prompts::{ render_prompt, PromptName };
+use crate::sections::{ check_sections, sections_prompt, ReportSection };
 use crate::utils::*;
 use chrono::{ DateTime, Utc };
-use github_flows::{ get_octo, octocrab::models::{ issues::Comment, issues::Issue }, GithubLogin };
+use crate::platform::{ get_page_text, github_octo, octocrab::models::{ issues::Comment, issues::Issue } };
 use log;
 use serde::Deserialize;
 use std::collections::{ HashMap, HashSet };
-use openai_flows::chat::ChatModel;
 
-pub async fn get_repo_info(about_repo: &str) -> Option<String> {
+pub async fn get_repo_info(about_repo: &str, llm: &LlmSession, creds: &GithubCredentials) -> Option<String> {
     #[derive(Deserialize)]
     struct CommunityProfile {
         description: Option<String>,
@@ -18,7 +22,13 @@ pub async fn get_repo_info(about_repo: &str) -> Option<String> {
     let community_profile_url = format!("repos/{}/community/profile", about_repo);
 
     let mut description = String::new();
-    let octocrab = get_octo(&GithubLogin::Default);
+    let octocrab = match github_octo(creds).await {
+        Ok(octocrab) => octocrab,
+        Err(_e) => {
+            log::error!("Error building a GitHub client: {}", _e);
+            return None;
+        }
+    };
 
     match octocrab.get::<CommunityProfile, _, ()>(&community_profile_url, None::<&()>).await {
         Ok(profile) => {
@@ -28,10 +38,10 @@ pub async fn get_repo_info(about_repo: &str) -> Option<String> {
     }
 
     let mut payload = String::new();
-    match get_readme_owner_repo(about_repo).await {
+    match get_readme_owner_repo(about_repo, creds).await {
         Some(content) => {
             let content = content.chars().take(20000).collect::<String>();
-            match analyze_readme(&content).await {
+            match analyze_readme(&content, about_repo, llm).await {
                 Some(summary) => {
                     payload = summary;
                 }
@@ -50,11 +60,11 @@ pub async fn get_repo_info(about_repo: &str) -> Option<String> {
         return Some(payload);
     }
 }
-pub async fn get_repo_overview_by_scraper(about_repo: &str) -> Option<String> {
-    let repo_home_url = format!("https://github.com/{}", about_repo);
+pub async fn get_repo_overview_by_scraper(about_repo: &str, llm: &LlmSession) -> Option<String> {
+    let repo_home_url = GithubHost::current().web(about_repo);
 
     let raw_text;
-    match web_scraper_flows::get_page_text(&repo_home_url).await {
+    match get_page_text(&repo_home_url).await {
         Ok(page_text) => {
             raw_text = page_text;
         }
@@ -71,13 +81,9 @@ pub async fn get_repo_overview_by_scraper(about_repo: &str) -> Option<String> {
         raw_text.to_string()
     };
 
-    let sys_prompt =
-        "Your task is to examine the textual content from a GitHub repo page, emphasizing the Header, About, Release, Contributors, Languages, and README sections. This process should be carried out objectively, focusing on factual information extraction from each segment. Avoid making subjective judgments or inferences. The data should be presented systematically, corresponding to each section. Please note, the provided text will be in a flattened format.";
+    let (sys_prompt, usr_prompt) = render_prompt(PromptName::Scraper, &[("page_text", &raw_text)]);
 
-    let usr_prompt =
-        &format!("I’ve obtained a flattened text from a GitHub repo page and require analysis of the following sections: 1) Header, with data on Fork, Star, Issues, Pull Request, etc.; 2) About, containing project description, keywords, number of stars, watchers, and forks; 3) Release, with details on the latest release and total releases; 4) Contributors, showing the number of contributors; 5) Languages, displaying the language composition in the project, and 6) README, which is usually a body of text describing the project, please summarize README when presenting result. Please extract and present data from these sections individually. Here is the text: {}", raw_text);
-
-    match chat_inner(sys_prompt, usr_prompt, 700, ChatModel::GPT35Turbo16K).await {
+    match llm.chat(Stage::Scraper, about_repo, &sys_prompt, &usr_prompt).await {
         Ok(r) => {
             return Some(r);
         }
@@ -89,54 +95,28 @@ pub async fn get_repo_overview_by_scraper(about_repo: &str) -> Option<String> {
 }
 
 pub async fn is_valid_owner_repo(
-    owner_repo: &str
+    owner_repo: &str,
+    llm: &LlmSession,
+    creds: &GithubCredentials
 ) -> anyhow::Result<(String, String, HashSet<String>)> {
-    #[derive(Deserialize)]
-    struct CommunityProfile {
-        description: Option<String>,
-        files: FileDetails,
-        updated_at: Option<DateTime<Utc>>,
-    }
-    #[derive(Debug, Deserialize)]
-    pub struct FileDetails {
-        readme: Option<Readme>,
-    }
-    #[derive(Debug, Deserialize)]
-    pub struct Readme {
-        url: Option<String>,
-    }
-    let community_profile_url = format!("repos/{}/community/profile", owner_repo);
-
-    let description;
-    let mut has_readme = false;
-    let octocrab = get_octo(&GithubLogin::Default);
-
-    match octocrab.get::<CommunityProfile, _, ()>(&community_profile_url, None::<&()>).await {
-        Ok(profile) => {
-            description = profile.description.as_ref().unwrap_or(&String::from("")).to_string();
-
-            has_readme = profile.files.readme
-                .as_ref()
-                .unwrap_or(&(Readme { url: None }))
-                .url.is_some();
-        }
-        Err(e) => {
-            log::error!("Error parsing Community Profile: {:?}", e);
-            return Err(anyhow::anyhow!("no Community Profile, so invalid owner/repo: {:?}", e));
+    // the community profile endpoint only answers for public repos, so existence is checked here
+    let description = match get_repo(owner_repo, creds).await {
+        Ok(repo) => repo.description.unwrap_or_default(),
+        Err(_e) => {
+            log::error!("Error looking up {}: {}", owner_repo, _e);
+            return Err(_e);
         }
-    }
+    };
 
     let mut payload = String::new();
 
-    if has_readme {
-        if let Some(content) = get_readme(owner_repo).await {
-            let content = content.chars().take(20000).collect::<String>();
-            match analyze_readme(&content).await {
-                Some(summary) => {
-                    payload = summary;
-                }
-                None => log::error!("Error parsing README.md: {}", owner_repo),
+    if let Some(content) = get_readme(owner_repo, creds).await {
+        let content = content.chars().take(20000).collect::<String>();
+        match analyze_readme(&content, owner_repo, llm).await {
+            Some(summary) => {
+                payload = summary;
             }
+            None => log::error!("Error parsing README.md: {}", owner_repo),
         }
     }
 
@@ -144,7 +124,7 @@ pub async fn is_valid_owner_repo(
         payload = description.clone();
     }
 
-    let contributors_set = match get_contributors(owner_repo).await {
+    let contributors_set = match get_contributors(owner_repo, creds).await {
         Ok(contributors) => contributors.into_iter().collect::<HashSet<String>>(),
         Err(_e) => HashSet::<String>::new(),
     };
@@ -152,78 +132,85 @@ pub async fn is_valid_owner_repo(
     Ok((owner_repo.to_string(), payload, contributors_set))
 }
 
+/// Summarizes the issues, one memory per issue and participant. An issue that fails is left out
+/// and counted as failed in the session's progress.
 pub async fn process_issues(
     inp_vec: Vec<Issue>,
     target_person: Option<String>,
     contributors_set: HashSet<String>,
-    token: Option<String>
-) -> anyhow::Result<HashMap<String, (String, String)>> {
+    project_summary: &str,
+    llm: &LlmSession,
+    creds: &GithubCredentials
+) -> anyhow::Result<Vec<GitMemory>> {
     use futures::future::join_all;
 
     let issue_futures: Vec<_> = inp_vec
         .into_iter()
         .map(|issue| {
             let target_person = target_person.clone();
-            let token = token.clone();
             let contributors_set = contributors_set.clone();
             async move {
-                let ve = analyze_issue_integrated(
-                    &issue,
-                    target_person,
-                    contributors_set,
-                    token
-                ).await.ok()?;
-                Some(ve)
+                match
+                    analyze_issue_integrated(
+                        &issue,
+                        target_person,
+                        contributors_set,
+                        project_summary,
+                        llm,
+                        creds
+                    ).await
+                {
+                    Ok(ve) => {
+                        llm.progress().summarized(1);
+                        Some(ve)
+                    }
+                    Err(_e) => {
+                        log::error!("Error summarizing issue {}: {}", issue.html_url, _e);
+                        llm.progress().failed(1);
+                        None
+                    }
+                }
             }
         })
         .collect();
 
     let results = join_all(issue_futures).await;
-    let mut issues_map = HashMap::<String, (String, String)>::new();
-
-    for result in results.into_iter().flatten() {
-        for item in result {
-            let (user_name, url, summary) = item;
-            // log::info!(
-            //     "User: {:?}, Url: {:?}, Summary: {:?}",
-            //     user_name.clone(),
-            //     url.clone(),
-            //     summary.clone()
-            // );
-            issues_map
-                .entry(user_name.clone())
-                .and_modify(|tup| {
-                    tup.0.push_str("\n");
-                    tup.0.push_str(&url);
-                    tup.1.push_str("\n");
-                    tup.1.push_str(&summary);
-                })
-                .or_insert((url.to_string(), summary.to_string()));
-        }
-    }
+    let memories = results.into_iter().flatten().flatten().collect::<Vec<GitMemory>>();
 
-    if issues_map.len() == 0 {
+    if memories.len() == 0 {
         anyhow::bail!("No issues processed");
     }
 
-    Ok(issues_map)
+    Ok(memories)
 }
 
-pub async fn analyze_readme(content: &str) -> Option<String> {
-    let sys_prompt_1 = &format!(
-        "Your task is to objectively analyze a GitHub profile and the README of their project. Focus on extracting factual information about the features of the project, and its stated objectives. Avoid making judgments or inferring subjective value."
-    );
+/// Per person, the newline separated urls and summaries of their memories, as the correlation
+/// step takes them.
+pub fn group_by_user(memories: &[GitMemory]) -> HashMap<String, (String, String)> {
+    let mut grouped = HashMap::<String, (String, String)>::new();
+    for memory in memories {
+        grouped
+            .entry(memory.name.clone())
+            .and_modify(|tup| {
+                tup.0.push_str("\n");
+                tup.0.push_str(&memory.source_url);
+                tup.1.push_str("\n");
+                tup.1.push_str(&memory.payload);
+            })
+            .or_insert((memory.source_url.clone(), memory.payload.clone()));
+    }
+    grouped
+}
 
+pub async fn analyze_readme(content: &str, owner_repo: &str, llm: &LlmSession) -> Option<String> {
     let content = if content.len() > 48_000 {
         squeeze_fit_remove_quoted(&content, 9_000, 0.7)
     } else {
         content.to_string()
     };
-    let usr_prompt_1 = &format!(
-        "Based on the profile and README provided: {content}, extract a concise summary detailing this project's factual significance in its domain, their areas of expertise, and the main features and goals of the project. Ensure the insights are objective and under 110 tokens."
-    );
+    let (sys_prompt_1, usr_prompt_1) = render_prompt(PromptName::Readme, &[("content", &content)]);
 
-    match chat_inner(sys_prompt_1, usr_prompt_1, 256, ChatModel::GPT35Turbo16K).await {
+    match llm.chat(Stage::Readme, owner_repo, &sys_prompt_1, &usr_prompt_1).await {
         Ok(r) => {
             return Some(r);
         }
@@ -238,8 +225,10 @@ pub async fn analyze_issue_integrated(
     issue: &Issue,
     target_person: Option<String>,
     contributors_set: HashSet<String>,
-    token: Option<String>
-) -> anyhow::Result<Vec<(String, String, String)>> {
+    project_summary: &str,
+    llm: &LlmSession,
+    creds: &GithubCredentials
+) -> anyhow::Result<Vec<GitMemory>> {
     let issue_creator_name = &issue.user.login;
     let issue_title = issue.title.to_string();
     let issue_number = issue.number;
@@ -265,24 +254,14 @@ pub async fn analyze_issue_integrated(
         issue_body
     );
 
-    let token_str = match token {
-        None => String::new(),
-        Some(t) => format!("&token={}", t.as_str()),
-    };
-
     // let comments_url = format!(
-    //     "{}/comments?sort=updated&order=desc&per_page=100{}",
-    //     issue_url.replace("https://api.github.com/", ""),
-    //     token_str
+    //     "{}/comments?sort=updated&order=desc&per_page=100",
+    //     issue_url.replace("https://api.github.com/", "")
     // );
-    let comments_url = format!(
-        "{}/comments?sort=updated&order=desc&per_page=100{}",
-        issue_url,
-        token_str
-    );
+    let comments_url = format!("{}/comments?sort=updated&order=desc&per_page=100", issue_url);
     // let octocrab = get_octo(&GithubLogin::Default);
 
-    let response = github_http_get(&comments_url).await?;
+    let response = github_http_get(&comments_url, creds).await?;
     let comments_obj = serde_json::from_slice::<Vec<Comment>>(&response)?;
 
     for comment in &comments_obj {
@@ -305,59 +284,40 @@ pub async fn analyze_issue_integrated(
         .clone()
         .map_or("key participants".to_string(), |t| t.to_string());
 
-    let sys_prompt_1 = &format!(
-        "Given the information that user '{issue_creator_name}' opened an issue titled '{issue_title}', your task is to deeply analyze the content of the issue posts. Distill the crux of the issue, the potential solutions suggested, and evaluate the significant contributions of the participants in resolving or progressing the discussion."
-    );
-
     let commenters_to_watch_str = if !target_str.is_empty() || issue_commenters_to_watch.len() == 0 {
         target_str
     } else {
         issue_commenters_to_watch.join(", ")
     };
 
-    // let usr_prompt_1 = &format!(
-    //     "Analyze the GitHub issue content: {}. Provide a concise analysis touching upon: The central problem discussed in the issue. The main solutions proposed or agreed upon. Highlight the role and significance of '{}' in contributing towards the resolution or progression of the discussion. Format the analysis into a flat JSON structure with one level of depth where each key maps directly to a single string value. Use the following template, replacing 'contributor_name' with the actual contributor's name, and 'summary' with your analysis of their contributions:
-    //     {{
-    //     \"contributor_name_1\": \"summary\",
-    //     \"contributor_name_2\": \"summary\"
-    //     }}",
-    //     all_text_from_issue,
-    //     commenters_to_watch_str
-    // );
-    let usr_prompt_1 = &format!(
-        "Analyze the GitHub issue content: {}. Provide a concise analysis touching upon: The central problem discussed in the issue. The main solutions proposed or agreed upon. Highlight the role and significance of '{}' in contributing towards the resolution or progression of the discussion. If the target person's contribution is negligible or non-existent, leave the corresponding summary blank. Format the analysis into a flat JSON structure with one level of depth where each key maps directly to a single string value. Use the following template, replacing 'contributor_name' with the actual contributor's name, and 'summary' with your analysis of their contributions or an empty string if their contribution is negligible: 
-        {{ 
-        \"contributor_name_1\": \"summary\",
-        \"contributor_name_2\": \"summary\"
-        }}",
-        all_text_from_issue,
-        commenters_to_watch_str
+    let (sys_prompt_1, usr_prompt_1) = render_prompt(
+        PromptName::Issue,
+        &[
+            ("project_context", &project_context(project_summary)),
+            ("issue_creator_name", issue_creator_name),
+            ("issue_title", &issue_title),
+            ("issue_text", &all_text_from_issue),
+            ("participants", &commenters_to_watch_str),
+        ]
     );
 
-    match chat_inner(sys_prompt_1, usr_prompt_1, 128, ChatModel::GPT35Turbo16K).await {
-        Ok(r) => {
-            let parsed = parse_issue_summary_from_json(&r)
-                .ok()
-                .unwrap_or_else(|| vec![]);
-
+    match llm.chat_json(Stage::Issue, &source_url, &sys_prompt_1, &usr_prompt_1, |_| Ok(())).await {
+        Ok(parsed) => {
             let out = parsed
                 .into_iter()
-                .map(|(user_name, summary)| { (user_name, source_url.clone(), summary) })
-                .collect::<Vec<(String, String, String)>>();
+                .filter_map(|(user_name, summary)| {
+                    summary.as_str().map(|s| GitMemory {
+                        memory_type: MemoryType::Issue,
+                        name: user_name,
+                        tag_line: issue_title.clone(),
+                        source_url: source_url.clone(),
+                        payload: s.to_string(),
+                        date: Some(issue.updated_at),
+                    })
+                })
+                .collect::<Vec<GitMemory>>();
 
             Ok(out)
-
-            // let out = format!("{} {}", issue_url, r);
-            // let name = target_person.map_or(issue_creator_name.to_string(), |t| t.to_string());
-            // let gm = GitMemory {
-            //     memory_type: MemoryType::Issue,
-            //     name: name,
-            //     tag_line: issue_title,
-            //     source_url: source_url,
-            //     payload: r,
-            // };
-
-            // Ok((out, gm))
         }
         Err(_e) => {
             log::error!("Error generating issue summary #{}: {}", issue_number, _e);
@@ -365,94 +325,164 @@ pub async fn analyze_issue_integrated(
         }
     }
 }
+/// Summarizes one commit from its patch. `project_context` is the sentence `project_context()`
+/// builds from the project summary.
+pub async fn summarize_commit(
+    commit_obj: &GitMemory,
+    project_context: &str,
+    llm: &LlmSession,
+    creds: &GithubCredentials
+) -> Option<String> {
+    let text = match get_commit_patch(&commit_obj.source_url, creds).await {
+        Ok(text) => text,
+        Err(_e) => {
+            log::error!("Error getting the patch of {}: {}", commit_obj.source_url, _e);
+            return None;
+        }
+    };
+
+    let stripped_texts = text.chars().take(24_000).collect::<String>();
+    // let stripped_texts = String::from_utf8(response).ok()?.chars().take(24_000).collect::<String>();
+    let (sys_prompt_1, usr_prompt_1) = render_prompt(
+        PromptName::Commit,
+        &[
+            ("project_context", project_context),
+            ("user_name", &commit_obj.name),
+            ("patch", &stripped_texts),
+            ("tag_line", &commit_obj.tag_line),
+        ]
+    );
+    llm.chat(Stage::Commit, &commit_obj.source_url, &sys_prompt_1, &usr_prompt_1).await.ok()
+}
+
+/// Summarizes the commits; the ones that fail to summarize are left out and counted as failed
+/// in the session's progress.
 pub async fn process_commits(
     inp_vec: Vec<GitMemory>,
-    commits_map: &mut HashMap<String, (String, String)>,
-    token: Option<String>
-) -> anyhow::Result<()> {
+    project_summary: &str,
+    llm: &LlmSession,
+    creds: &GithubCredentials
+) -> Vec<GitMemory> {
     use futures::future::join_all;
-    let token_query = match token {
-        None => String::new(),
-        Some(t) => format!("?token={}", t),
-    };
+    let project_context = project_context(project_summary);
 
     let commit_futures: Vec<_> = inp_vec
         .into_iter()
         .map(|commit_obj| {
-            let url = format!("{}.patch{}", commit_obj.source_url, token_query);
+            let project_context = project_context.clone();
             async move {
-                let response = github_http_get(&url).await.ok()?;
-                let text = String::from_utf8(response).ok()?;
-
-                let stripped_texts = text.chars().take(24_000).collect::<String>();
-                // let stripped_texts = String::from_utf8(response).ok()?.chars().take(24_000).collect::<String>();
-                let user_name = commit_obj.name.clone();
-                let sys_prompt_1 = format!(
-                    "Given a commit patch from user {user_name}, analyze its content. Focus on changes that substantively alter code or functionality. A good analysis prioritizes the commit message for clues on intent and refrains from overstating the impact of minor changes. Aim to provide a balanced, fact-based representation that distinguishes between major and minor contributions to the project. Keep your analysis concise."
-                );
-                let tag_line = commit_obj.tag_line;
-                let usr_prompt_1 = format!(
-                    "Analyze the commit patch: {stripped_texts}, and its description: {tag_line}. Summarize the main changes, but only emphasize modifications that directly affect core functionality. A good summary is fact-based, derived primarily from the commit message, and avoids over-interpretation. It recognizes the difference between minor textual changes and substantial code adjustments. Conclude by evaluating the realistic impact of {user_name}'s contributions in this commit on the project. Limit the response to 110 tokens."
-                );
-                let summary = chat_inner(
-                    &sys_prompt_1,
-                    &usr_prompt_1,
-                    128,
-                    ChatModel::GPT35Turbo16K
-                ).await.ok()?;
-                // log::info!("Summary: {:?}", summary.clone());
-                Some((commit_obj.name, commit_obj.source_url, summary))
+                let summary = match
+                    summarize_commit(&commit_obj, &project_context, llm, creds).await
+                {
+                    Some(summary) => summary,
+                    None => {
+                        llm.progress().failed(1);
+                        return None;
+                    }
+                };
+                llm.progress().summarized(1);
+                Some(GitMemory {
+                    payload: summary,
+                    ..commit_obj
+                })
             }
         })
         .collect();
 
-    let results = join_all(commit_futures).await;
-    for result in results.into_iter().flatten() {
-        let (user_name, url, summary): (String, String, String) = result;
-        // log::info!(
-        //     "User: {:?}, Url: {:?}, Summary: {:?}",
-        //     user_name.clone(),
-        //     url.clone(),
-        //     summary.clone()
-        // );
-        commits_map
-            .entry(user_name.clone())
-            .and_modify(|tup| {
-                tup.0.push_str("\n");
-                tup.0.push_str(&url);
-                tup.1.push_str("\n");
-                tup.1.push_str(&summary);
-            })
-            .or_insert((url, summary.to_string()));
-    }
-
-    Ok(())
+    join_all(commit_futures).await.into_iter().flatten().collect()
 }
 
 pub async fn correlate_commits_issues_sparse(
     _commits_summary: &str,
     _issues_summary: &str,
-    target_person: &str
+    target_person: &str,
+    project_summary: &str,
+    sections: &[ReportSection],
+    llm: &LlmSession
+) -> Option<String> {
+    let (system_prompt, user_input) = render_prompt(
+        PromptName::Correlation,
+        &[
+            ("project_context", &project_context(project_summary)),
+            ("commits_summary", _commits_summary),
+            ("issues_summary", _issues_summary),
+            ("target_person", target_person),
+            ("sections", &sections_prompt(sections)),
+        ]
+    );
+
+    match
+        llm.chat_json(Stage::Correlation, target_person, &system_prompt, &user_input, |parsed|
+            check_sections(parsed, sections)
+        ).await
+    {
+        Ok(parsed) => Some(serde_json::Value::Object(parsed).to_string()),
+        Err(_e) => {
+            log::error!("Error correlating activities of {}: {}", target_person, _e);
+            None
+        }
+    }
+}
+
+pub async fn summarize_repo_activity(
+    owner_repo: &str,
+    project_summary: &str,
+    user_reports: &str,
+    closed_issues: &str,
+    llm: &LlmSession
+) -> Option<String> {
+    let user_reports = squeeze_fit_post_texts(user_reports, 10_000, 0.7);
+    let closed_issues = squeeze_fit_post_texts(closed_issues, 1_500, 1.0);
+
+    let (sys_prompt_1, usr_prompt_1) = render_prompt(
+        PromptName::ExecutiveSummary,
+        &[
+            ("owner_repo", owner_repo),
+            ("project_summary", project_summary),
+            ("user_reports", &user_reports),
+            ("closed_issues", &closed_issues),
+        ]
+    );
+
+    match llm.chat(Stage::ExecutiveSummary, owner_repo, &sys_prompt_1, &usr_prompt_1).await {
+        Ok(r) => Some(r),
+        Err(_e) => {
+            log::error!("Error generating executive summary for {}: {}", owner_repo, _e);
+            None
+        }
+    }
+}
+
+pub async fn draft_release_notes(
+    owner_repo: &str,
+    n_days: u16,
+    project_summary: &str,
+    commits: &str,
+    closed_issues: &str,
+    llm: &LlmSession
 ) -> Option<String> {
-    let system_prompt =
-        "You're a GitHub data analysis bot. You're tasked to analyze a GitHub contributor's activity data over the week to detect both key impactful contributions and connections between commits and issues. Highlight specific code changes, resolutions, and improvements.";
-
-    let user_input = &format!(
-        r#"From {_commits_summary}, {_issues_summary}. Analyze the key technical contributions made by {target_person} this week and summarize the information into a flat JSON structure with just one level of depth. Each key in the JSON should map directly to a single string value describing the contribution or observation in a full sentence or a short paragraph without using nested objects or arrays. If no information is available for a point, provide an empty string as the value. 
-Please ensure that the JSON output does not include any Markdown formatting, such as code block syntax ("```") or escaped characters (like "\\n" for new lines). The output should be plain JSON that can be parsed directly without any preprocessing.
-
-Your JSON response should use the following keys with appropriate string values:
-{{
-"impactful": "Provide a single string value summarizing impactful contributions and their interconnections.",
-"alignment": "Provide a single string value explaining how the contributions align with the project's goals.",
-"patterns": "Provide a single string value identifying any recurring patterns or trends in the contributions.",
-"synergy": "Provide a single string value discussing the synergy between individual and collective advancement.",
-"significance": "Provide a single string value commenting on the significance of the contributions."
-}}
-Ensure that the JSON is properly formatted, with correct escaping of special characters, and is ready to be parsed by a JSON parser that expects RFC8259-compliant JSON. Avoid adding any non-JSON content or formatting."#
+    let commits = squeeze_fit_post_texts(commits, 10_000, 0.7);
+    let closed_issues = squeeze_fit_post_texts(closed_issues, 1_500, 1.0);
+    let project_context = project_context(project_summary);
+
+    let (sys_prompt_1, usr_prompt_1) = render_prompt(
+        PromptName::ReleaseNotes,
+        &[
+            ("project_context", &project_context),
+            ("owner_repo", owner_repo),
+            ("n_days", &n_days.to_string()),
+            ("commits", &commits),
+            ("closed_issues", &closed_issues),
+        ]
     );
 
-    chat_inner_async(system_prompt, user_input, 500, "gpt-3.5-turbo-1106").await.ok()
+    match llm.chat(Stage::ReleaseNotes, owner_repo, &sys_prompt_1, &usr_prompt_1).await {
+        Ok(r) => Some(r),
+        Err(_e) => {
+            log::error!("Error drafting release notes for {}: {}", owner_repo, _e);
+            None
+        }
+    }
 }
 
 /* pub async fn github_http_fetch(token: &str, url: &str) -> Option<Vec<u8>> {
@@ -480,3 +510,40 @@ Ensure that the JSON is properly formatted, with correct escaping of special cha
         }
     }
 } */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::LlmConfig;
+
+    fn dry_run() -> LlmSession {
+        let mut config = LlmConfig::with_defaults();
+        config.dry_run = true;
+        LlmSession::new(config)
+    }
+
+    fn long_lines(prefix: &str, n: usize) -> String {
+        (0..n).map(|i| format!("{prefix} {i} changed something")).collect::<Vec<String>>().join("\n")
+    }
+
+    #[test]
+    fn release_notes_prompt_fits_oversized_input() {
+        let llm = dry_run();
+        let notes = futures::executor::block_on(
+            draft_release_notes(
+                "o/r",
+                7,
+                "a project",
+                &long_lines("commit", 10_000),
+                &long_lines("#issue", 3_000),
+                &llm
+            )
+        );
+
+        assert!(notes.is_some());
+        let planned = llm.planned_calls();
+        assert_eq!(planned.len(), 1);
+        // 10k of commits and 1.5k of closed issues, plus the prompt itself
+        assert!(planned[0].prompt_tokens < 12_500);
+    }
+}
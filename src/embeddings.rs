@@ -0,0 +1,205 @@
+//! Turns text into vectors for semantic search. The backend is picked with `EMBEDDING_BACKEND`:
+//!
+//! - `openai` (the default): the OpenAI embeddings API, `EMBEDDING_MODEL` defaulting to
+//!   `text-embedding-3-small`;
+//! - `http`: any OpenAI-compatible embeddings endpoint at `EMBEDDING_URL`, such as a local model
+//!   server, with `EMBEDDING_API_KEY` sent as a bearer token if set;
+//! - `local`: hashed word counts computed in process. No model and no network, so it only matches
+//!   shared words, but it is free and deterministic, which is what tests and mock setups want.
+
+use crate::platform::http_post;
+use async_openai::{ types::CreateEmbeddingRequestArgs, Client };
+use serde::Deserialize;
+use serde_json::json;
+
+const DEFAULT_MODEL: &str = "text-embedding-3-small";
+const LOCAL_DIMENSIONS: usize = 512;
+// inputs per request, well under what the OpenAI API accepts
+const BATCH_SIZE: usize = 64;
+
+#[derive(Debug, Clone)]
+pub enum EmbeddingBackend {
+    OpenAi {
+        model: String,
+    },
+    Http {
+        url: String,
+        model: String,
+        api_key: Option<String>,
+    },
+    Local,
+}
+
+impl EmbeddingBackend {
+    pub fn from_env() -> anyhow::Result<EmbeddingBackend> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let model = var("EMBEDDING_MODEL").unwrap_or_else(|| String::from(DEFAULT_MODEL));
+
+        match var("EMBEDDING_BACKEND").as_deref().unwrap_or("openai") {
+            "openai" => Ok(EmbeddingBackend::OpenAi { model }),
+            "http" =>
+                Ok(EmbeddingBackend::Http {
+                    url: var("EMBEDDING_URL").ok_or_else(||
+                        anyhow::anyhow!("EMBEDDING_URL must be set for the http embedding backend")
+                    )?,
+                    model,
+                    api_key: var("EMBEDDING_API_KEY"),
+                }),
+            "local" => Ok(EmbeddingBackend::Local),
+            other => Err(anyhow::anyhow!("Unknown EMBEDDING_BACKEND: {other}")),
+        }
+    }
+
+    /// Names the vector space; vectors from different backends or models don't compare.
+    pub fn id(&self) -> String {
+        match self {
+            EmbeddingBackend::OpenAi { model } => format!("openai:{model}"),
+            EmbeddingBackend::Http { url, model, .. } => format!("http:{url}:{model}"),
+            EmbeddingBackend::Local => format!("local:{LOCAL_DIMENSIONS}"),
+        }
+    }
+
+    /// One vector per text, in order.
+    pub async fn embed(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(BATCH_SIZE) {
+            let embedded = match self {
+                EmbeddingBackend::OpenAi { model } => embed_openai(model, batch).await?,
+                EmbeddingBackend::Http { url, model, api_key } => {
+                    embed_http(url, model, api_key.as_deref(), batch).await?
+                }
+                EmbeddingBackend::Local => batch.iter().map(|text| embed_local(text)).collect(),
+            };
+            if embedded.len() != batch.len() {
+                return Err(
+                    anyhow::anyhow!(
+                        "Asked for {} embeddings, got {} from {}",
+                        batch.len(),
+                        embedded.len(),
+                        self.id()
+                    )
+                );
+            }
+            vectors.extend(embedded);
+        }
+        Ok(vectors)
+    }
+}
+
+async fn embed_openai(model: &str, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+    let request = CreateEmbeddingRequestArgs::default().model(model).input(texts.to_vec()).build()?;
+    let mut response = Client::new().embeddings().create(request).await?;
+    response.data.sort_by_key(|embedding| embedding.index);
+    Ok(
+        response.data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect()
+    )
+}
+
+async fn embed_http(
+    url: &str,
+    model: &str,
+    api_key: Option<&str>,
+    texts: &[String]
+) -> anyhow::Result<Vec<Vec<f32>>> {
+    #[derive(Deserialize)]
+    struct Embedding {
+        #[serde(default)]
+        index: usize,
+        embedding: Vec<f32>,
+    }
+    #[derive(Deserialize)]
+    struct EmbeddingResponse {
+        data: Vec<Embedding>,
+    }
+
+    let mut headers = vec![("Content-Type", String::from("application/json"))];
+    if let Some(key) = api_key {
+        headers.push(("Authorization", format!("Bearer {key}")));
+    }
+    let body = json!({"model": model, "input": texts}).to_string();
+    let (status, response) = http_post(url, &headers, body.as_bytes()).await?;
+    if !(200..300).contains(&status) {
+        return Err(
+            anyhow::anyhow!(
+                "The embedding endpoint answered {}: {}",
+                status,
+                String::from_utf8_lossy(&response).chars().take(200).collect::<String>()
+            )
+        );
+    }
+
+    let mut parsed = serde_json::from_slice::<EmbeddingResponse>(&response)?;
+    parsed.data.sort_by_key(|embedding| embedding.index);
+    Ok(
+        parsed.data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect()
+    )
+}
+
+/// Each lowercased word adds one to a slot picked by its hash, with a sign from another bit of
+/// the hash so collisions tend to cancel out; the result is scaled to unit length.
+fn embed_local(text: &str) -> Vec<f32> {
+    let mut vector = vec![0f32; LOCAL_DIMENSIONS];
+    let words = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() > 1)
+        .map(|word| word.to_lowercase());
+    for word in words {
+        let hash = stable_hash(&word);
+        let slot = (hash % (LOCAL_DIMENSIONS as u64)) as usize;
+        vector[slot] += if (hash >> 63) == 0 { 1.0 } else { -1.0 };
+    }
+    normalize(vector)
+}
+
+/// FNV-1a: the same across runs and platforms, unlike the standard library's hashers.
+pub fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ (byte as u64)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector
+        .iter()
+        .map(|x| x * x)
+        .sum::<f32>()
+        .sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| {
+            *x /= norm;
+        });
+    }
+    vector
+}
+
+/// Cosine similarity; 0 for vectors of different lengths or no length.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| x * y)
+        .sum::<f32>();
+    let norm_a = a
+        .iter()
+        .map(|x| x * x)
+        .sum::<f32>()
+        .sqrt();
+    let norm_b = b
+        .iter()
+        .map(|x| x * x)
+        .sum::<f32>()
+        .sqrt();
+    match norm_a * norm_b {
+        n if n > 0.0 => dot / n,
+        _ => 0.0,
+    }
+}
@@ -0,0 +1,89 @@
+//! The flows.network glue: the webhook endpoint with its request handler, and the hourly cron
+//! trigger that runs scheduled reports and the daily ingest. Everything they call into is platform
+//! independent.
+
+use crate::api::{ route, ApiRequest };
+use crate::ingest::run_due_ingests;
+use crate::schedules::run_due_schedules;
+use dotenv::dotenv;
+use flowsnet_platform_sdk::logger;
+use schedule_flows::{ schedule_cron_job, schedule_handler };
+use serde_json::Value;
+use std::collections::HashMap;
+use webhook_flows::{ create_endpoint, request_handler, send_response };
+
+#[no_mangle]
+#[tokio::main(flavor = "current_thread")]
+pub async fn on_deploy() {
+    create_endpoint().await;
+    // hourly; each schedule decides for itself whether it is due
+    schedule_cron_job(String::from("0 * * * *"), String::from("report_schedules")).await;
+}
+
+// one handler per method, since the platform doesn't pass the method to the handler itself
+#[request_handler(GET)]
+async fn get_handler(
+    _headers: Vec<(String, String)>,
+    _subpath: String,
+    _qry: HashMap<String, Value>,
+    _body: Vec<u8>
+) {
+    handle("GET", _headers, _subpath, _qry, _body).await;
+}
+
+#[request_handler(POST)]
+async fn post_handler(
+    _headers: Vec<(String, String)>,
+    _subpath: String,
+    _qry: HashMap<String, Value>,
+    _body: Vec<u8>
+) {
+    handle("POST", _headers, _subpath, _qry, _body).await;
+}
+
+async fn handle(
+    method: &str,
+    _headers: Vec<(String, String)>,
+    _subpath: String,
+    _qry: HashMap<String, Value>,
+    _body: Vec<u8>
+) {
+    dotenv().ok();
+    logger::init();
+
+    let OPENAI_API_KEY = std::env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set");
+
+    let res = route(ApiRequest {
+        method: method.to_string(),
+        path: _subpath,
+        query: _qry,
+        headers: _headers,
+        body: _body,
+    }).await;
+
+    send_response(res.status, res.headers, res.body);
+
+    // the caller already has its answer; a started report job runs to the end from here
+    if let Some(job) = res.background {
+        job.run().await;
+    }
+}
+
+#[schedule_handler]
+async fn on_tick(_body: Vec<u8>) {
+    dotenv().ok();
+    logger::init();
+
+    match run_due_schedules(chrono::Utc::now()).await {
+        Ok(runs) => {
+            for run in runs.iter().filter(|run| run.status != "not_due") {
+                log::info!("Schedule {}: {}", run.id, run.status);
+            }
+        }
+        Err(_e) => log::error!("Error loading the report schedules: {}", _e),
+    }
+
+    for run in run_due_ingests(chrono::Utc::now()).await.into_iter().flatten() {
+        log::info!("Ingested {}: {} summaries stored", run.owner_repo, run.stored);
+    }
+}
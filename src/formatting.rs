@@ -0,0 +1,151 @@
+//! Flat, one-line-per-item text renderings of the typed GitHub data, for feeding into prompts or
+//! plain-text replies. These reproduce the "Name: x, Login: y, ..." lines the fetchers used to
+//! return directly.
+
+use crate::github_data_fetchers::{ IssueSearchItem, RepoSearchItem, UserProfile, UserRepo };
+
+// long texts keep their head and tail
+fn truncate_middle(text: &str, max_len: usize, head: usize, tail: usize) -> Option<String> {
+    let count = text.chars().count();
+    (text.len() > max_len).then(|| {
+        text.chars()
+            .take(head)
+            .chain(text.chars().skip(count.saturating_sub(tail)))
+            .collect::<String>()
+    })
+}
+
+pub fn format_issue(issue: &IssueSearchItem) -> String {
+    let title_str = format!("Title: {},", issue.title);
+    let url_str = format!("Url: {}", issue.url);
+    let date = issue.created_at.date_naive();
+
+    let author_str = match &issue.author {
+        Some(auth) => format!("Author: {},", auth),
+        None => String::new(),
+    };
+
+    let assignees_str = match issue.assignees.is_empty() {
+        true => String::new(),
+        false => format!("Assignees: {},", issue.assignees.join(", ")),
+    };
+
+    let state_str = match &issue.state {
+        Some(s) => format!("State: {},", s),
+        None => String::new(),
+    };
+
+    let body_str = match truncate_middle(&issue.body, 180, 100, 80) {
+        Some(truncated_body) => format!("Body: {}", truncated_body),
+        None => format!("Body: {},", issue.body),
+    };
+
+    let assoc_str = match &issue.author_association {
+        Some(association) => format!("Author Association: {}", association),
+        None => String::new(),
+    };
+
+    format!(
+        "{title_str} {url_str} Created At: {date} {author_str} {assignees_str}  {state_str} {body_str} {assoc_str}"
+    )
+}
+
+pub fn format_issues(issues: &[IssueSearchItem]) -> String {
+    let mut out = String::from("ISSUES \n");
+    for issue in issues {
+        out.push_str(&format_issue(issue));
+        out.push_str("\n");
+    }
+    out
+}
+
+pub fn format_repo(repo: &RepoSearchItem) -> String {
+    let name_str = format!("Name: {},", repo.name);
+
+    let desc_str = match &repo.description {
+        Some(desc) =>
+            match truncate_middle(desc, 300, 180, 120) {
+                Some(truncated_desc) => format!("Description: {truncated_desc}"),
+                None => format!("Description: {desc},"),
+            }
+        None => String::new(),
+    };
+
+    format!(
+        "{name_str} {desc_str} Url: {} Created At: {} Stars: {}, Forks: {}",
+        repo.url,
+        repo.created_at.date_naive(),
+        repo.stars,
+        repo.forks
+    )
+}
+
+pub fn format_repos(repos: &[RepoSearchItem]) -> String {
+    let mut out = String::from("REPOSITORY \n");
+    for repo in repos {
+        out.push_str(&format_repo(repo));
+        out.push_str("\n");
+    }
+    out
+}
+
+pub fn format_user(user: &UserProfile) -> String {
+    let optional = |label: &str, value: &Option<String>, separator: &str| match value {
+        Some(v) => format!("{label}: {v}{separator}"),
+        None => String::new(),
+    };
+
+    let date_str = match &user.created_at {
+        Some(date) => format!("Created At: {},", date.date_naive()),
+        None => String::new(),
+    };
+
+    format!(
+        "{} Login: {}, {} {} {} {} {} {date_str} {}",
+        optional("Name", &user.name, ","),
+        user.login,
+        optional("Url", &user.url, ","),
+        optional("Twitter", &user.twitter_username, ","),
+        optional("Bio", &user.bio, ","),
+        optional("Company", &user.company, ","),
+        optional("Location", &user.location, ","),
+        optional("Email", &user.email, "")
+    )
+}
+
+pub fn format_users(users: &[UserProfile]) -> String {
+    let mut out = String::from("USERS: \n");
+    for user in users {
+        out.push_str(&format_user(user));
+        out.push_str("\n");
+    }
+    out
+}
+
+pub fn format_user_profile(user: &UserProfile) -> String {
+    format!("USER_profile: \n{}\n", format_user(user))
+}
+
+pub fn format_user_repos(language: &str, repos: &[UserRepo]) -> String {
+    let mut out = format!("Repos in {language}:\n");
+    for repo in repos {
+        let description_str = match &repo.description {
+            Some(description) => format!("Description: {},", description),
+            None => String::new(),
+        };
+
+        let stars_str = match repo.stars {
+            0 => String::new(),
+            count => format!("Stars: {count}"),
+        };
+
+        out.push_str(
+            &format!(
+                "Repo: {} {description_str} {stars_str} Commits: {}\n",
+                repo.name,
+                repo.commits
+            )
+        );
+    }
+    out
+}
@@ -0,0 +1,192 @@
+//! Authenticating as a GitHub App installation. The deployment signs a short-lived JWT with the
+//! App's private key, trades it for an installation token of the installation covering the repo
+//! owner, and reuses that token until shortly before it expires.
+//!
+//! Configured through `GITHUB_APP_ID` and `GITHUB_APP_PRIVATE_KEY` (the PEM text; `\n` escapes
+//! are accepted for single-line settings). `GITHUB_APP_INSTALLATION_ID` pins one installation
+//! instead of looking it up by owner.
+
+use crate::platform::{ build_octo, octocrab };
+use chrono::{ DateTime, Duration, Utc };
+use jsonwebtoken::{ encode, Algorithm, EncodingKey, Header };
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::sync::{ Mutex, OnceLock };
+
+pub struct GithubApp {
+    app_id: u64,
+    key: EncodingKey,
+    installation_id: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct JwtClaims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct Installation {
+    id: u64,
+}
+
+// installation tokens by installation id, and installation ids by owner login
+fn token_cache() -> &'static Mutex<HashMap<u64, InstallationToken>> {
+    static TOKENS: OnceLock<Mutex<HashMap<u64, InstallationToken>>> = OnceLock::new();
+    TOKENS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn installation_cache() -> &'static Mutex<HashMap<String, u64>> {
+    static INSTALLATIONS: OnceLock<Mutex<HashMap<String, u64>>> = OnceLock::new();
+    INSTALLATIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+impl GithubApp {
+    /// The App this deployment authenticates as, if one is configured. A misconfigured App is
+    /// logged once and treated as absent.
+    pub fn configured() -> Option<&'static GithubApp> {
+        static APP: OnceLock<Option<GithubApp>> = OnceLock::new();
+        APP.get_or_init(|| {
+            match GithubApp::from_env() {
+                Ok(app) => app,
+                Err(_e) => {
+                    log::error!("Ignoring the GitHub App settings: {}", _e);
+                    None
+                }
+            }
+        }).as_ref()
+    }
+
+    pub fn from_env() -> anyhow::Result<Option<GithubApp>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+
+        let (app_id, pem) = match (var("GITHUB_APP_ID"), var("GITHUB_APP_PRIVATE_KEY")) {
+            (Some(app_id), Some(pem)) => (app_id, pem),
+            (None, None) => {
+                return Ok(None);
+            }
+            _ => {
+                return Err(
+                    anyhow::anyhow!("GITHUB_APP_ID and GITHUB_APP_PRIVATE_KEY must be set together")
+                );
+            }
+        };
+
+        let app_id = app_id
+            .trim()
+            .parse::<u64>()
+            .map_err(|_e| anyhow::anyhow!("GITHUB_APP_ID is not a number: {app_id}"))?;
+        let key = EncodingKey::from_rsa_pem(pem.replace("\\n", "\n").as_bytes()).map_err(|_e|
+            anyhow::anyhow!("GITHUB_APP_PRIVATE_KEY is not an RSA PEM key: {_e}")
+        )?;
+        let installation_id = match var("GITHUB_APP_INSTALLATION_ID") {
+            Some(id) =>
+                Some(
+                    id
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|_e| anyhow::anyhow!("GITHUB_APP_INSTALLATION_ID is not a number: {id}"))?
+                ),
+            None => None,
+        };
+
+        Ok(Some(GithubApp { app_id, key, installation_id }))
+    }
+
+    /// A JWT identifying the App itself, good for the app/installations endpoints only.
+    fn jwt(&self) -> anyhow::Result<String> {
+        let now = Utc::now().timestamp();
+        // backdated against clock drift; GitHub refuses JWTs valid for more than ten minutes
+        let claims = JwtClaims {
+            iat: now - 60,
+            exp: now + 9 * 60,
+            iss: self.app_id.to_string(),
+        };
+        Ok(encode(&Header::new(Algorithm::RS256), &claims, &self.key)?)
+    }
+
+    fn app_octo(&self) -> anyhow::Result<octocrab::Octocrab> {
+        build_octo(Some(self.jwt()?))
+    }
+
+    /// The pinned installation, or else the one installed on `owner`'s account.
+    async fn installation_id(&self, owner: Option<&str>) -> anyhow::Result<u64> {
+        if let Some(id) = self.installation_id {
+            return Ok(id);
+        }
+        let owner = match owner {
+            Some(owner) => owner.to_lowercase(),
+            None => {
+                return Err(
+                    anyhow::anyhow!(
+                        "No GitHub App installation to use: name a repo owner or set GITHUB_APP_INSTALLATION_ID"
+                    )
+                );
+            }
+        };
+
+        let cached = installation_cache()
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(&owner).copied());
+        if let Some(id) = cached {
+            return Ok(id);
+        }
+
+        let octocrab = self.app_octo()?;
+        let mut found = None;
+        for route in [format!("orgs/{owner}/installation"), format!("users/{owner}/installation")] {
+            match octocrab.get::<Installation, _, ()>(&route, None::<&()>).await {
+                Ok(installation) => {
+                    found = Some(installation.id);
+                    break;
+                }
+                Err(_e) => log::debug!("No installation at {}: {:?}", route, _e),
+            }
+        }
+
+        match found {
+            Some(id) => {
+                if let Ok(mut cache) = installation_cache().lock() {
+                    cache.insert(owner, id);
+                }
+                Ok(id)
+            }
+            None => Err(anyhow::anyhow!("The GitHub App is not installed on {owner}")),
+        }
+    }
+
+    /// An installation token for `owner`, minted anew once the cached one is about to expire.
+    pub async fn installation_token(&self, owner: Option<&str>) -> anyhow::Result<String> {
+        let id = self.installation_id(owner).await?;
+
+        let cached = token_cache()
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(&id).cloned());
+        // leaves room for a long report to finish on the same token
+        if let Some(cached) = cached.filter(|t| t.expires_at - Duration::minutes(5) > Utc::now()) {
+            return Ok(cached.token);
+        }
+
+        let route = format!("app/installations/{id}/access_tokens");
+        let fresh: InstallationToken = self
+            .app_octo()?
+            .post(&route, None::<&()>).await
+            .map_err(|_e|
+                anyhow::anyhow!("Failed to get a token for GitHub App installation {id}: {_e}")
+            )?;
+
+        if let Ok(mut cache) = token_cache().lock() {
+            cache.insert(id, fresh.clone());
+        }
+        Ok(fresh.token)
+    }
+}
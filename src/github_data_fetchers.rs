@@ -1,10 +1,14 @@
+use crate::credentials::GithubCredentials;
+use crate::github_host::GithubHost;
+use crate::graphql::GraphqlRequest;
+use crate::llm::{ LlmSession, Stage };
+use crate::prompts::{ render_prompt, PromptName };
 use crate::utils::*;
 use chrono::{ DateTime, Duration, Utc };
 use derivative::Derivative;
-use github_flows::octocrab::models::{ issues::Issue, Repository, User };
-use github_flows::{ get_octo, octocrab, GithubLogin };
-use serde::{ Deserialize, Serialize };
-use openai_flows::chat::ChatModel;
+use crate::platform::octocrab::models::{ issues::Issue, Repository, User };
+use crate::platform::{ github_octo, octocrab };
+use serde::{ de::DeserializeOwned, Deserialize, Serialize };
 
 #[derive(Derivative, Serialize, Deserialize, Debug, Clone)]
 pub struct GitMemory {
@@ -17,8 +21,11 @@ pub struct GitMemory {
     pub source_url: String,
     #[derivative(Default(value = "String::from(\"\")"))]
     pub payload: String,
+    /// When the item saw the activity it was picked up for.
+    #[serde(default)]
+    pub date: Option<DateTime<Utc>>,
 }
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MemoryType {
     Commit,
     Issue,
@@ -26,15 +33,31 @@ pub enum MemoryType {
     Meta,
 }
 
-pub async fn get_user_profile(user: &str) -> Option<User> {
+impl MemoryType {
+    /// `commit`, `issue`, `discussion` or `meta`, in any case.
+    pub fn from_name(name: &str) -> Option<MemoryType> {
+        match name.to_lowercase().as_str() {
+            "commit" => Some(MemoryType::Commit),
+            "issue" => Some(MemoryType::Issue),
+            "discussion" => Some(MemoryType::Discussion),
+            "meta" => Some(MemoryType::Meta),
+            _ => None,
+        }
+    }
+}
+
+pub async fn get_user_profile(user: &str, creds: &GithubCredentials) -> Option<User> {
     let user_profile_url = format!("users/{user}");
 
-    let octocrab = get_octo(&GithubLogin::Default);
+    let octocrab = github_octo(creds).await.ok()?;
 
     octocrab.get::<User, _, ()>(&user_profile_url, None::<&()>).await.ok()
 }
 
-pub async fn get_user_data_by_login(login: &str) -> anyhow::Result<String> {
+pub async fn get_user_data_by_login(
+    login: &str,
+    creds: &GithubCredentials
+) -> anyhow::Result<Option<UserProfile>> {
     #[derive(Debug, Deserialize)]
     struct User {
         name: Option<String>,
@@ -61,13 +84,11 @@ pub async fn get_user_data_by_login(login: &str) -> anyhow::Result<String> {
         data: Option<RepositoryOwner>,
     }
 
-    let mut out = String::from("USER_profile: \n");
-
-    let query = format!(
+    let query =
         r#"
-        query {{
-            repositoryOwner(login: "{login}") {{
-                ... on User {{
+        query($login: String!) {
+            repositoryOwner(login: $login) {
+                ... on User {
                     name
                     login
                     url
@@ -77,81 +98,123 @@ pub async fn get_user_data_by_login(login: &str) -> anyhow::Result<String> {
                     location
                     createdAt
                     email
-                }}
-            }}
-        }}
-        "#
-    );
+                }
+            }
+        }
+        "#;
+
+    let res: UserRoot = GraphqlRequest::new(query).var("login", login).send(creds).await?;
+
+    Ok(
+        res.data
+            .and_then(|d| d.repository_owner)
+            .and_then(|user| {
+                Some(UserProfile {
+                    login: user.login?,
+                    name: user.name,
+                    url: user.url,
+                    twitter_username: user.twitter_username,
+                    bio: user.bio.filter(|bio| !bio.is_empty()),
+                    company: user.company,
+                    location: user.location,
+                    created_at: user.created_at,
+                    email: user.email,
+                })
+            })
+    )
+}
 
-    let octocrab = get_octo(&GithubLogin::Default);
-
-    let res: UserRoot = octocrab.graphql::<UserRoot>(&query).await?;
-    if let Some(repository_owner) = &res.data {
-        if let Some(user) = &repository_owner.repository_owner {
-            let login_str = match &user.login {
-                Some(login) => format!("Login: {},", login),
-                None => String::new(),
-            };
-
-            let name_str = match &user.name {
-                Some(name) => format!("Name: {},", name),
-                None => String::new(),
-            };
-
-            let url_str = match &user.url {
-                Some(url) => format!("Url: {},", url),
-                None => String::new(),
-            };
-
-            let twitter_str = match &user.twitter_username {
-                Some(twitter) => format!("Twitter: {},", twitter),
-                None => String::new(),
-            };
-
-            let bio_str = match &user.bio {
-                Some(bio) if bio.is_empty() => String::new(),
-                Some(bio) => format!("Bio: {},", bio),
-                None => String::new(),
-            };
-
-            let company_str = match &user.company {
-                Some(company) => format!("Company: {},", company),
-                None => String::new(),
-            };
-
-            let location_str = match &user.location {
-                Some(location) => format!("Location: {},", location),
-                None => String::new(),
-            };
-
-            let date_str = match &user.created_at {
-                Some(date) => { format!("Created At: {},", date.date_naive().to_string()) }
-                None => String::new(),
-            };
-
-            let email_str = match &user.email {
-                Some(email) => format!("Email: {}", email),
-                None => String::new(),
-            };
-
-            out.push_str(
-                &format!(
-                    "{name_str} {login_str} {url_str} {twitter_str} {bio_str} {company_str} {location_str} {date_str} {email_str}\n"
-                )
-            );
+/// The repo as `repos/{owner}/{repo}` describes it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoInfo {
+    pub full_name: String,
+    pub description: Option<String>,
+    pub private: bool,
+    pub default_branch: Option<String>,
+    pub html_url: String,
+}
+
+/// Why a repo couldn't be read. GitHub answers 404 both for repos that don't exist and for
+/// private repos the credentials can't see, so `NotFound` covers both.
+#[derive(Debug)]
+pub enum RepoAccessError {
+    NotFound(String),
+    Forbidden(String, String),
+}
+
+impl RepoAccessError {
+    pub fn status(&self) -> u16 {
+        match self {
+            RepoAccessError::NotFound(_) => 404,
+            RepoAccessError::Forbidden(_, _) => 403,
         }
     }
+}
 
-    Ok(out)
+impl std::fmt::Display for RepoAccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepoAccessError::NotFound(owner_repo) =>
+                write!(
+                    f,
+                    "{owner_repo} was not found. If it is private, use a token or GitHub App installation that can read it."
+                ),
+            RepoAccessError::Forbidden(owner_repo, message) =>
+                write!(f, "GitHub refused access to {owner_repo}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for RepoAccessError {}
+
+/// Looks the repo up through the REST API, which works alike for public and private repos.
+/// 404 and 401/403 answers come back as a `RepoAccessError`.
+pub async fn get_repo(owner_repo: &str, creds: &GithubCredentials) -> anyhow::Result<RepoInfo> {
+    let url = GithubHost::current().api(&format!("repos/{owner_repo}"));
+
+    match github_http_get(&url, creds).await {
+        Ok(body) => Ok(serde_json::from_slice::<RepoInfo>(&body)?),
+        Err(_e) =>
+            match _e.downcast_ref::<GithubHttpError>() {
+                Some(err) if err.status == 404 => {
+                    Err(RepoAccessError::NotFound(owner_repo.to_string()).into())
+                }
+                Some(err) if err.status == 401 || err.status == 403 => {
+                    Err(RepoAccessError::Forbidden(owner_repo.to_string(), err.message.clone()).into())
+                }
+                _ => Err(_e),
+            }
+    }
 }
 
-pub async fn get_contributors(owner_repo: &str) -> Result<Vec<String>, octocrab::Error> {
+/// A commit's patch, fetched from the API as `application/vnd.github.patch` rather than from the
+/// web `.patch` url, which private repos only serve to a browser session. Takes the commit's
+/// `html_url`, `https://{host}/{owner}/{repo}/commit/{sha}`.
+pub async fn get_commit_patch(commit_url: &str, creds: &GithubCredentials) -> anyhow::Result<String> {
+    let segments = commit_url.trim_end_matches('/').split('/').collect::<Vec<&str>>();
+    let route = match segments.iter().rposition(|s| *s == "commit") {
+        Some(i) if i >= 2 && i + 1 < segments.len() =>
+            format!("repos/{}/{}/commits/{}", segments[i - 2], segments[i - 1], segments[i + 1]),
+        _ => {
+            return Err(anyhow::anyhow!("Not a commit url: {commit_url}"));
+        }
+    };
+
+    let url = GithubHost::current().api(&route);
+    let body = github_http_get_as(&url, "application/vnd.github.patch", creds).await?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+pub async fn get_contributors(
+    owner_repo: &str,
+    creds: &GithubCredentials
+) -> anyhow::Result<Vec<String>> {
     #[derive(Debug, Deserialize)]
     struct GithubUser {
         login: String,
     }
     let mut contributors = Vec::new();
-    let octocrab = get_octo(&GithubLogin::Default);
+    let octocrab = github_octo(creds).await?;
     'outer: for n in 1..50 {
         log::info!("contributors loop {}", n);
 
@@ -179,7 +242,7 @@ pub async fn get_contributors(owner_repo: &str) -> Result<Vec<String>, octocrab:
     Ok(contributors)
 }
 
-pub async fn get_readme(owner_repo: &str) -> Option<String> {
+pub async fn get_readme(owner_repo: &str, creds: &GithubCredentials) -> Option<String> {
     #[derive(Deserialize, Debug)]
     struct GithubReadme {
         content: Option<String>,
@@ -187,7 +250,13 @@ pub async fn get_readme(owner_repo: &str) -> Option<String> {
 
     let readme_url = format!("repos/{owner_repo}/readme");
 
-    let octocrab = get_octo(&GithubLogin::Default);
+    let octocrab = match github_octo(creds).await {
+        Ok(octocrab) => octocrab,
+        Err(_e) => {
+            log::error!("Error building a GitHub client: {}", _e);
+            return None;
+        }
+    };
 
     match octocrab.get::<GithubReadme, _, ()>(&readme_url, None::<&()>).await {
         Ok(readme) => {
@@ -220,7 +289,7 @@ pub async fn get_readme(owner_repo: &str) -> Option<String> {
         }
     }
 }
-pub async fn get_readme_owner_repo(about_repo: &str) -> Option<String> {
+pub async fn get_readme_owner_repo(about_repo: &str, creds: &GithubCredentials) -> Option<String> {
     #[derive(Deserialize, Debug)]
     struct GithubReadme {
         content: Option<String>,
@@ -228,7 +297,13 @@ pub async fn get_readme_owner_repo(about_repo: &str) -> Option<String> {
 
     let readme_url = format!("repos/{about_repo}/readme");
 
-    let octocrab = get_octo(&GithubLogin::Default);
+    let octocrab = match github_octo(creds).await {
+        Ok(octocrab) => octocrab,
+        Err(_e) => {
+            log::error!("Error building a GitHub client: {}", _e);
+            return None;
+        }
+    };
 
     match octocrab.get::<GithubReadme, _, ()>(&readme_url, None::<&()>).await {
         Ok(readme) => {
@@ -262,76 +337,128 @@ pub async fn get_readme_owner_repo(about_repo: &str) -> Option<String> {
     }
 }
 
-pub async fn get_issues_in_range(
-    owner_repo: &str,
-    user_name: Option<String>,
-    range: u16,
-    token: Option<String>
-) -> Option<(usize, Vec<Issue>)> {
+/// The period a report covers. Both bounds go into the search qualifiers, so a window that starts
+/// where the previous one ended neither overlaps it nor leaves a gap.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ReportWindow {
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+}
+
+impl ReportWindow {
+    /// The `n_days` up to now.
+    pub fn last_days(n_days: u16) -> Self {
+        let until = Utc::now();
+        ReportWindow {
+            since: until - Duration::days(n_days as i64),
+            until,
+        }
+    }
+
+    pub fn between(since: DateTime<Utc>, until: DateTime<Utc>) -> Self {
+        ReportWindow { since, until }
+    }
+
+    /// Days covered, rounded up, for headings and prompts.
+    pub fn days(&self) -> u16 {
+        let hours = (self.until - self.since).num_hours().max(0);
+        ((hours + 23) / 24).min(u16::MAX as i64) as u16
+    }
+
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        self.since <= at && at < self.until
+    }
+
+    // search ranges are inclusive, so the last second belongs to the next window
+    pub fn search_range(&self) -> String {
+        let format = "%Y-%m-%dT%H:%M:%SZ";
+        format!(
+            "{}..{}",
+            self.since.format(format),
+            (self.until - Duration::seconds(1)).format(format)
+        )
+    }
+}
+
+/// A REST search route for `query` on `page`. The query is percent-encoded whole, so whatever a
+/// user or repo name holds stays in `q` and can't add parameters of its own.
+pub fn search_route(kind: &str, query: &str, params: &str, page: usize) -> String {
+    format!("search/{kind}?q={}&{params}&page={page}", urlencoding::encode(query))
+}
+
+/// GitHub search returns at most this many results for a query.
+const SEARCH_RESULT_LIMIT: u64 = 1000;
+
+/// Every result of a REST search, 100 per page. A failed page, or more matches than search can
+/// return, is an error rather than a silently shorter list.
+async fn search_all<T: DeserializeOwned>(
+    kind: &str,
+    query: &str,
+    params: &str,
+    creds: &GithubCredentials
+) -> anyhow::Result<Vec<T>> {
     #[derive(Debug, Deserialize)]
     struct Page<T> {
         pub items: Vec<T>,
         pub total_count: Option<u64>,
     }
 
-    let n_days_ago = (Utc::now() - Duration::days(range as i64))
-        .format("%Y-%m-%dT%H:%M:%SZ")
-        .to_string();
+    let octocrab = github_octo(creds).await?;
+    let mut out = Vec::<T>::new();
 
-    let user_str = user_name.map_or(String::new(), |u| format!("involves:{}", u));
-
-    let query = format!("repo:{owner_repo} is:issue {user_str} updated:>{n_days_ago}");
-    let encoded_query = urlencoding::encode(&query);
-    let token_str = match token {
-        None => String::new(),
-        Some(t) => format!("&token={}", t.as_str()),
-    };
-
-    let octocrab = get_octo(&GithubLogin::Default);
+    for page in 1.. {
+        let url_str = search_route(kind, query, &format!("{params}&per_page=100"), page);
+        let search_page = match octocrab.get::<Page<T>, _, ()>(&url_str, None::<&()>).await {
+            Ok(search_page) => search_page,
+            Err(_e) => {
+                return Err(anyhow::anyhow!("Error getting page {} of {} search: {}", page, kind, _e));
+            }
+        };
 
-    let mut out = Vec::new();
+        let total = search_page.total_count;
+        if let Some(total) = total.filter(|total| *total > SEARCH_RESULT_LIMIT) {
+            return Err(
+                anyhow::anyhow!(
+                    "{} {} match `{}`, more than the {} search can return; use a shorter window",
+                    total,
+                    kind,
+                    query,
+                    SEARCH_RESULT_LIMIT
+                )
+            );
+        }
 
-    for _n in 1..3 {
-        let url_str = format!(
-            "search/issues?q={}&sort=updated&order=desc&per_page=100&page={}{}",
-            encoded_query,
-            _n,
-            token_str
-        );
-        // let url_str = format!(
-        //     "https://api.github.com/search/issues?q={}&sort=updated&order=desc&per_page=100{token_str}",
-        //     encoded_query
-        // );
-
-        match octocrab.get::<Page<Issue>, _, ()>(&url_str, None::<&()>).await {
-            Err(e) => {
-                log::error!("Error getting paginated issues: {:?}", e);
-                continue;
-            }
-            Ok(issue_page) => {
-                out.extend(issue_page.items.clone().into_iter());
-                if &issue_page.items.len() < &100 {
-                    break;
-                }
-            }
+        let n = search_page.items.len();
+        out.extend(search_page.items);
+        if n < 100 || total.map_or(false, |total| (out.len() as u64) >= total) {
+            break;
         }
     }
+
+    Ok(out)
+}
+
+pub async fn get_issues_in_range(
+    owner_repo: &str,
+    user_name: Option<String>,
+    window: &ReportWindow,
+    creds: &GithubCredentials
+) -> anyhow::Result<(usize, Vec<Issue>)> {
+    let user_str = user_name.map_or(String::new(), |u| format!("involves:{}", u));
+
+    let query = format!("repo:{owner_repo} is:issue {user_str} updated:{}", window.search_range());
+    let out = search_all::<Issue>("issues", &query, "sort=updated&order=desc", creds).await?;
+
     let count = out.len();
-    Some((count, out))
+    Ok((count, out))
 }
 
 pub async fn get_commits_in_range_search(
     owner_repo: &str,
     user_name: Option<String>,
-    range: u16,
-    token: Option<String>
-) -> Option<(usize, Vec<GitMemory>)> {
-    #[derive(Debug, Deserialize)]
-    struct Page<T> {
-        pub items: Vec<T>,
-        pub total_count: Option<u64>,
-    }
-
+    window: &ReportWindow,
+    creds: &GithubCredentials
+) -> anyhow::Result<(usize, Vec<GitMemory>)> {
     #[derive(Debug, Deserialize, Serialize, Clone)]
     struct User {
         login: String,
@@ -348,65 +475,54 @@ pub async fn get_commits_in_range_search(
     #[derive(Serialize, Deserialize, Debug)]
     struct CommitDetails {
         message: String,
-        // committer: CommitUserDetails,
+        committer: Option<CommitUserDetails>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct CommitUserDetails {
+        date: Option<DateTime<Utc>>,
     }
-    let token_str = match &token {
-        None => String::from(""),
-        Some(t) => format!("&token={}", t.as_str()),
-    };
     let author_str = match &user_name {
         None => String::from(""),
-        Some(t) => format!("%20author:{}", t.as_str()),
+        Some(t) => format!(" author:{}", t.as_str()),
     };
-    let now = Utc::now();
-    let n_days_ago = (now - Duration::days(range as i64)).date_naive();
-
-    let query = format!("repo:{}{}%20committer-date:>{}", owner_repo, author_str, n_days_ago);
-    // let encoded_query = urlencoding::encode(&query);
-    let mut git_memory_vec = vec![];
-    let octocrab = get_octo(&GithubLogin::Default);
-
-    for _n in 1..3 {
-        let url_str = format!(
-            "search/commits?q={}&sort=committer-date&order=desc&per_page=100&page={}{}",
-            query,
-            _n,
-            token_str
-        );
-        // let url_str = format!(
-        //     "https://api.github.com/search/commits?q={}&sort=author-date&order=desc&per_page=100{token_str}",
-        //     encoded_query
-        // );
-
-        match octocrab.get::<Page<GithubCommit>, _, ()>(&url_str, None::<&()>).await {
-            Err(e) => {
-                log::error!("Error parsing commits: {:?}", e);
-            }
-            Ok(commits_page) => {
-                for commit in &commits_page.items {
-                    if let Some(author) = &commit.author {
-                        // log::info!("commit author: {:?}", author.clone());
-                        git_memory_vec.push(GitMemory {
-                            memory_type: MemoryType::Commit,
-                            name: author.login.clone(),
-                            tag_line: commit.commit.message.clone(),
-                            source_url: commit.html_url.clone(),
-                            payload: String::from(""),
-                        });
-                    }
-                }
-                if &commits_page.items.len() < &100 {
-                    break;
-                }
-            }
-        }
-    }
+    let query = format!(
+        "repo:{}{} committer-date:{}",
+        owner_repo,
+        author_str,
+        window.search_range()
+    );
+    let commits = search_all::<GithubCommit>(
+        "commits",
+        &query,
+        "sort=committer-date&order=desc",
+        creds
+    ).await?;
+
+    let git_memory_vec = commits
+        .into_iter()
+        .filter_map(|commit| {
+            let author = commit.author?;
+            Some(GitMemory {
+                memory_type: MemoryType::Commit,
+                name: author.login,
+                tag_line: commit.commit.message,
+                source_url: commit.html_url,
+                payload: String::from(""),
+                date: commit.commit.committer.as_ref().and_then(|c| c.date),
+            })
+        })
+        .collect::<Vec<GitMemory>>();
     let count = git_memory_vec.len();
 
-    Some((count, git_memory_vec))
+    Ok((count, git_memory_vec))
 }
 
-pub async fn get_user_repos_in_language(user: &str, language: &str) -> Option<Vec<Repository>> {
+pub async fn get_user_repos_in_language(
+    user: &str,
+    language: &str,
+    creds: &GithubCredentials
+) -> Option<Vec<Repository>> {
     #[derive(Debug, Deserialize)]
     struct Page<T> {
         pub items: Vec<T>,
@@ -414,16 +530,21 @@ pub async fn get_user_repos_in_language(user: &str, language: &str) -> Option<Ve
     }
 
     let query = format!("user:{} language:{} sort:stars", user, language);
-    let encoded_query = urlencoding::encode(&query);
 
     let mut out: Vec<Repository> = vec![];
     let mut total_pages = None;
     let mut current_page = 1;
 
     loop {
-        let url_str = format!("search/repositories?q={}&page={}", encoded_query, current_page);
+        let url_str = search_route("repositories", &query, "per_page=30", current_page);
 
-        let octocrab = get_octo(&GithubLogin::Default);
+        let octocrab = match github_octo(creds).await {
+            Ok(octocrab) => octocrab,
+            Err(_e) => {
+                log::error!("Error building a GitHub client: {}", _e);
+                return None;
+            }
+        };
 
         match octocrab.get::<Page<Repository>, _, ()>(&url_str, None::<&()>).await {
             Err(_e) => {
@@ -456,7 +577,74 @@ pub async fn get_user_repos_in_language(user: &str, language: &str) -> Option<Ve
     }
 }
 
-pub async fn get_user_repos_gql(user_name: &str, language: &str) -> Option<String> {
+/// Repos of an organization that saw a push in the last `n_days`, most recently pushed first.
+/// Pages through the org's repos until they get older than that. A missing or hidden org comes
+/// back as a `RepoAccessError`.
+pub async fn get_org_repos_active_since(
+    org: &str,
+    n_days: u16,
+    creds: &GithubCredentials
+) -> anyhow::Result<Vec<String>> {
+    let since = Utc::now() - Duration::days(n_days as i64);
+    let mut active = Vec::<String>::new();
+
+    for page in 1.. {
+        let url = GithubHost::current().api(
+            &format!("orgs/{org}/repos?sort=pushed&direction=desc&per_page=100&page={page}")
+        );
+        let body = match github_http_get(&url, creds).await {
+            Ok(body) => body,
+            Err(_e) =>
+                match _e.downcast_ref::<GithubHttpError>() {
+                    Some(err) if err.status == 404 => {
+                        return Err(RepoAccessError::NotFound(org.to_string()).into());
+                    }
+                    Some(err) if err.status == 401 || err.status == 403 => {
+                        return Err(RepoAccessError::Forbidden(org.to_string(), err.message.clone()).into());
+                    }
+                    _ => {
+                        return Err(_e.context(format!("Failed to list the repos of {org}")));
+                    }
+                }
+        };
+        let repos = serde_json::from_slice::<Vec<Repository>>(&body)?;
+        let full_page = repos.len() == 100;
+
+        for repo in repos {
+            // sorted by push date, so the first stale repo ends the list
+            if repo.pushed_at.map_or(true, |pushed| pushed < since) {
+                return Ok(active);
+            }
+            if repo.archived.unwrap_or(false) {
+                continue;
+            }
+            if let Some(full_name) = repo.full_name {
+                active.push(full_name);
+            }
+        }
+        if !full_page {
+            break;
+        }
+    }
+
+    Ok(active)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRepo {
+    pub name: String,
+    pub description: Option<String>,
+    pub stars: u32,
+    /// Commits on the default branch; 0 for an empty repo.
+    pub commits: u32,
+}
+
+/// The user's repos in `language`, most starred first.
+pub async fn get_user_repos_gql(
+    user_name: &str,
+    language: &str,
+    creds: &GithubCredentials
+) -> Option<Vec<UserRepo>> {
     #[derive(Debug, Deserialize)]
     struct Root {
         data: Data,
@@ -476,7 +664,7 @@ pub async fn get_user_repos_gql(user_name: &str, language: &str) -> Option<Strin
     pub struct Node {
         pub name: String,
         #[serde(rename = "defaultBranchRef")]
-        default_branch_ref: BranchRef,
+        default_branch_ref: Option<BranchRef>,
         stargazers: Stargazers,
         pub description: Option<String>,
     }
@@ -493,106 +681,171 @@ pub async fn get_user_repos_gql(user_name: &str, language: &str) -> Option<Strin
     #[derive(Debug, Deserialize)]
     struct History {
         #[serde(rename = "totalCount")]
-        total_count: i32,
+        total_count: u32,
     }
 
     #[derive(Debug, Deserialize)]
     struct Stargazers {
         #[serde(rename = "totalCount")]
-        total_count: i32,
+        total_count: u32,
     }
 
-    let query = format!(
+    let query =
         r#"
-    query {{
-        search(query: "user:{} language:{}", type: REPOSITORY, first: 100) {{
-            nodes {{
-                ... on Repository {{
+    query($q: String!) {
+        search(query: $q, type: REPOSITORY, first: 100) {
+            nodes {
+                ... on Repository {
                     name
-                    defaultBranchRef {{
-                        target {{
-                            ... on Commit {{
-                                history(first: 0) {{
+                    defaultBranchRef {
+                        target {
+                            ... on Commit {
+                                history(first: 0) {
                                     totalCount
-                                }}
-                            }}
-                        }}
-                    }}
+                                }
+                            }
+                        }
+                    }
                     description
-                    stargazers {{
+                    stargazers {
                         totalCount
-                    }}
-                }}
-            }}
-        }}
-    }}
-    "#,
-        user_name,
-        language
-    );
-
-    let octocrab = get_octo(&GithubLogin::Default);
-    let mut out = format!("Repos in {language}:\n");
+                    }
+                }
+            }
+        }
+    }
+    "#;
+    let search_query = format!("user:{} language:{}", user_name, language);
 
-    match octocrab.graphql::<Root>(&query).await {
-        Err(e) => log::error!("Failed to parse the response: {}", e),
+    match GraphqlRequest::new(query).var("q", search_query).send::<Root>(creds).await {
+        Err(e) => {
+            log::error!("Failed to parse the response: {}", e);
+            None
+        }
         Ok(repos) => {
-            let mut repos_sorted: Vec<&Node> = repos.data.search.nodes.iter().collect();
-            repos_sorted.sort_by(|a, b| b.stargazers.total_count.cmp(&a.stargazers.total_count));
+            log::info!("Found {} repositories", repos.data.search.nodes.len());
+
+            let mut out = repos.data.search.nodes
+                .into_iter()
+                .map(|node| UserRepo {
+                    name: node.name,
+                    description: node.description,
+                    stars: node.stargazers.total_count,
+                    commits: node.default_branch_ref.map_or(0, |b| b.target.history.total_count),
+                })
+                .collect::<Vec<UserRepo>>();
+            out.sort_by(|a, b| b.stars.cmp(&a.stars));
+
+            Some(out)
+        }
+    }
+}
 
-            for repo in repos_sorted {
-                let name_str = format!("Repo: {}", repo.name);
+/// One page of GitHub search results. Pass `end_cursor` back as the `cursor` of the next call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchPage<T> {
+    pub items: Vec<T>,
+    pub total_count: u64,
+    pub end_cursor: Option<String>,
+    pub has_next_page: bool,
+}
 
-                let description_str = match &repo.description {
-                    Some(description) => format!("Description: {},", description),
-                    None => String::new(),
-                };
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssueSearchItem {
+    pub url: String,
+    pub number: u64,
+    pub state: Option<String>,
+    pub title: String,
+    pub body: String,
+    pub author: Option<String>,
+    pub assignees: Vec<String>,
+    pub author_association: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
 
-                let stars_str = match repo.stargazers.total_count {
-                    0 => String::new(),
-                    count => format!("Stars: {count}"),
-                };
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoSearchItem {
+    pub name: String,
+    pub description: Option<String>,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    pub stars: u32,
+    pub forks: u32,
+}
 
-                let commits_str = format!(
-                    "Commits: {}",
-                    repo.default_branch_ref.target.history.total_count
-                );
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub login: String,
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub twitter_username: Option<String>,
+    pub bio: Option<String>,
+    pub company: Option<String>,
+    pub location: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub email: Option<String>,
+}
 
-                let temp = format!("{name_str} {description_str} {stars_str} {commits_str}\n");
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscussionSearchItem {
+    pub title: String,
+    pub url: String,
+    pub author: Option<String>,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub upvotes: u32,
+    pub comments: Vec<DiscussionComment>,
+}
 
-                out.push_str(&temp);
-            }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscussionComment {
+    pub author: Option<String>,
+    pub body: String,
+}
 
-            log::info!("Found {} repositories", repos.data.search.nodes.len());
-        }
-    }
-    Some(out)
+#[derive(Debug, Deserialize)]
+struct GqlPageInfo {
+    #[serde(rename = "endCursor")]
+    end_cursor: Option<String>,
+    #[serde(rename = "hasNextPage")]
+    has_next_page: Option<bool>,
 }
 
-pub async fn search_issue(search_query: &str) -> anyhow::Result<String> {
-    #[derive(Debug, Deserialize, Clone)]
-    pub struct User {
-        login: Option<String>,
-    }
+#[derive(Debug, Deserialize)]
+struct GqlLogin {
+    login: Option<String>,
+}
 
-    #[derive(Debug, Deserialize, Clone)]
+// GraphQL caps `first` at 100
+fn page_size(limit: u16) -> u16 {
+    limit.clamp(1, 100)
+}
+
+pub async fn search_issue(
+    search_query: &str,
+    limit: u16,
+    cursor: Option<&str>,
+    creds: &GithubCredentials
+) -> anyhow::Result<SearchPage<IssueSearchItem>> {
+    #[derive(Debug, Deserialize)]
     struct AssigneeNode {
-        node: Option<User>,
+        node: Option<GqlLogin>,
     }
 
-    #[derive(Debug, Deserialize, Clone)]
+    #[derive(Debug, Deserialize)]
     struct AssigneeEdge {
         edges: Option<Vec<Option<AssigneeNode>>>,
     }
 
-    #[derive(Debug, Deserialize, Clone)]
+    #[derive(Debug, Deserialize)]
     struct Issue {
         url: Option<String>,
         number: Option<u64>,
         state: Option<String>,
         title: Option<String>,
         body: Option<String>,
-        author: Option<User>,
+        author: Option<GqlLogin>,
         assignees: Option<AssigneeEdge>,
         #[serde(rename = "authorAssociation")]
         author_association: Option<String>,
@@ -607,19 +860,13 @@ pub async fn search_issue(search_query: &str) -> anyhow::Result<String> {
         node: Option<Issue>,
     }
 
-    #[derive(Debug, Deserialize, Clone)]
-    struct PageInfo {
-        #[serde(rename = "endCursor")]
-        end_cursor: Option<String>,
-        #[serde(rename = "hasNextPage")]
-        has_next_page: Option<bool>,
-    }
-
     #[derive(Debug, Deserialize)]
     struct SearchResult {
+        #[serde(rename = "issueCount")]
+        issue_count: Option<u64>,
         edges: Option<Vec<Option<IssueNode>>>,
         #[serde(rename = "pageInfo")]
-        page_info: Option<PageInfo>,
+        page_info: Option<GqlPageInfo>,
     }
 
     #[derive(Debug, Deserialize)]
@@ -632,171 +879,91 @@ pub async fn search_issue(search_query: &str) -> anyhow::Result<String> {
         data: Option<IssueSearch>,
     }
 
-    let mut out = String::from("ISSUES \n");
-
-    let mut cursor: Option<String> = None;
-    let mut has_next_page = true;
-
-    loop {
-        let query = format!(
-            r#"
-            query {{
-                search(query: "{}", type: ISSUE, first: 100{}) {{
-                    edges {{
-                        node {{
-                            ... on Issue {{
-                                url
-                                number
-                                state
-                                title
-                                body
-                                author {{
-                                    login
-                                }}
-                                assignees(first: 100) {{
-                                    edges {{
-                                        node {{
-                                            login
-                                        }}
-                                    }}
-                                }}
-                                authorAssociation
-                                createdAt
-                                updatedAt
-                            }}
-                        }}
-                    }}
-                    pageInfo {{
-                        endCursor
-                        hasNextPage
-                      }}
-                }}
-            }}
-            "#,
-            search_query,
-            cursor.as_ref().map_or(String::new(), |c| format!(r#", after: "{}""#, c))
-        );
-
-        let octocrab = get_octo(&GithubLogin::Default);
-        let response: IssueRoot = octocrab.graphql(&query).await?;
-
-        if let Some(search) = response.data.as_ref().and_then(|d| d.search.as_ref()) {
-            if let Some(edges) = &search.edges {
-                for edge in edges.iter().filter_map(|e| e.as_ref()) {
-                    if let Some(issue) = &edge.node {
-                        let date = match issue.created_at {
-                            Some(date) => date.date_naive().to_string(),
-                            None => {
-                                continue;
-                            }
-                        };
-                        let title_str = match &issue.title {
-                            Some(title) => format!("Title: {},", title),
-                            None => String::new(),
-                        };
-                        let url_str = match &issue.url {
-                            Some(u) => format!("Url: {}", u),
-                            None => String::new(),
-                        };
-
-                        let author_str = match issue.clone().author.and_then(|a| a.login) {
-                            Some(auth) => format!("Author: {},", auth),
-                            None => String::new(),
-                        };
-
-                        let assignees_str = {
-                            let assignee_names = issue.assignees
-                                .as_ref()
-                                .and_then(|e| e.edges.as_ref())
-                                .map_or(Vec::new(), |assignee_edges| {
-                                    assignee_edges
-                                        .iter()
-                                        .filter_map(|edge| {
-                                            edge.as_ref().and_then(|actual_edge| {
-                                                actual_edge.node
-                                                    .as_ref()
-                                                    .and_then(|user| {
-                                                        user.login
-                                                            .as_ref()
-                                                            .map(|login_str| login_str.as_str())
-                                                    })
-                                            })
-                                        })
-                                        .collect::<Vec<&str>>()
-                                });
-
-                            if !assignee_names.is_empty() {
-                                format!("Assignees: {},", assignee_names.join(", "))
-                            } else {
-                                String::new()
-                            }
-                        };
-
-                        let state_str = match &issue.state {
-                            Some(s) => format!("State: {},", s),
-                            None => String::new(),
-                        };
-
-                        let body_str = match &issue.body {
-                            Some(body_text) if body_text.len() > 180 => {
-                                let truncated_body = body_text
-                                    .chars()
-                                    .take(100)
-                                    .chain(body_text.chars().skip(body_text.chars().count() - 80))
-                                    .collect::<String>();
-
-                                format!("Body: {}", truncated_body)
-                            }
-                            Some(body_text) => format!("Body: {},", body_text),
-                            None => String::new(),
-                        };
-
-                        let assoc_str = match &issue.author_association {
-                            Some(association) => { format!("Author Association: {}", association) }
-                            None => String::new(),
-                        };
-
-                        let temp = format!(
-                            "{title_str} {url_str} Created At: {date} {author_str} {assignees_str}  {state_str} {body_str} {assoc_str}"
-                        );
-
-                        out.push_str(&temp);
-                        out.push_str("\n");
-                    } else {
-                        continue;
-                    }
-                }
-            }
-
-            if let Some(page_info) = &search.page_info {
-                if let Some(has_next_page) = page_info.has_next_page {
-                    if has_next_page {
-                        match &page_info.end_cursor {
-                            Some(end_cursor) => {
-                                cursor = Some(end_cursor.clone());
-                                log::info!(
-                                    "Fetched a page, moving to next page with cursor: {}",
-                                    end_cursor
-                                );
-                                continue;
+    let query =
+        r#"
+        query($q: String!, $first: Int!, $after: String) {
+            search(query: $q, type: ISSUE, first: $first, after: $after) {
+                issueCount
+                edges {
+                    node {
+                        ... on Issue {
+                            url
+                            number
+                            state
+                            title
+                            body
+                            author {
+                                login
                             }
-                            None => {
-                                log::error!(
-                                    "Warning: hasNextPage is true, but endCursor is None. This might result in missing data."
-                                );
-                                break;
+                            assignees(first: 100) {
+                                edges {
+                                    node {
+                                        login
+                                    }
+                                }
                             }
+                            authorAssociation
+                            createdAt
+                            updatedAt
                         }
                     }
                 }
+                pageInfo {
+                    endCursor
+                    hasNextPage
+                }
             }
         }
-    }
-
-    Ok(out)
+        "#;
+
+    let response: IssueRoot = GraphqlRequest::new(query)
+        .var("q", search_query)
+        .var("first", page_size(limit))
+        .var_opt("after", cursor)
+        .send(creds).await?;
+    let search = response.data
+        .and_then(|d| d.search)
+        .ok_or_else(|| anyhow::Error::msg("Missing search in the response"))?;
+
+    let items = search.edges
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|edge| edge.and_then(|e| e.node))
+        .filter_map(|issue| {
+            Some(IssueSearchItem {
+                url: issue.url?,
+                number: issue.number?,
+                state: issue.state,
+                title: issue.title.unwrap_or_default(),
+                body: issue.body.unwrap_or_default(),
+                author: issue.author.and_then(|a| a.login),
+                assignees: issue.assignees
+                    .and_then(|a| a.edges)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|edge| edge.and_then(|e| e.node).and_then(|u| u.login))
+                    .collect(),
+                author_association: issue.author_association,
+                created_at: issue.created_at?,
+                updated_at: issue.updated_at,
+            })
+        })
+        .collect::<Vec<IssueSearchItem>>();
+
+    Ok(SearchPage {
+        total_count: search.issue_count.unwrap_or(items.len() as u64),
+        end_cursor: search.page_info.as_ref().and_then(|p| p.end_cursor.clone()),
+        has_next_page: search.page_info.and_then(|p| p.has_next_page).unwrap_or(false),
+        items,
+    })
 }
 
-pub async fn search_repository(search_query: &str) -> anyhow::Result<String> {
+pub async fn search_repository(
+    search_query: &str,
+    limit: u16,
+    cursor: Option<&str>,
+    creds: &GithubCredentials
+) -> anyhow::Result<SearchPage<RepoSearchItem>> {
     #[derive(Debug, Deserialize)]
     struct Payload {
         data: Option<Data>,
@@ -809,9 +976,11 @@ pub async fn search_repository(search_query: &str) -> anyhow::Result<String> {
 
     #[derive(Debug, Deserialize)]
     struct Search {
+        #[serde(rename = "repositoryCount")]
+        repository_count: Option<u64>,
         edges: Option<Vec<Option<Edge>>>,
         #[serde(rename = "pageInfo")]
-        page_info: Option<PageInfo>,
+        page_info: Option<GqlPageInfo>,
     }
 
     #[derive(Debug, Deserialize)]
@@ -837,126 +1006,72 @@ pub async fn search_repository(search_query: &str) -> anyhow::Result<String> {
         total_count: Option<u32>,
     }
 
-    #[derive(Debug, Deserialize)]
-    struct PageInfo {
-        #[serde(rename = "endCursor")]
-        end_cursor: Option<String>,
-        #[serde(rename = "hasNextPage")]
-        has_next_page: Option<bool>,
-    }
-
-    let octocrab = get_octo(&GithubLogin::Default);
-    let mut out = String::from("REPOSITORY \n");
-
-    let mut cursor: Option<String> = None;
-    let mut has_next_page = true;
-
-    while has_next_page {
-        let query = format!(
-            r#"
-            query {{
-                search(query: "{search_query}", type: REPOSITORY, first: 100{after}) {{
-                    edges {{
-                        node {{
-                            ... on Repository {{
-                                name
-                                description
-                                url
-                                createdAt
-                                stargazers {{
-                                    totalCount
-                                }}
-                                forkCount
-                            }}
-                        }}
-                    }}
-                    pageInfo {{
-                        endCursor
-                        hasNextPage
-                    }}
-                }}
-            }}
-            "#,
-            search_query = search_query,
-            after = cursor.as_ref().map_or(String::new(), |c| format!(r#", after: "{}""#, c))
-        );
-
-        let response: Payload = octocrab.graphql(&query).await?;
-
-        if let Some(data) = &response.data {
-            if let Some(search) = &data.search {
-                if let Some(edges) = &search.edges {
-                    for edge_option in edges {
-                        if let Some(edge) = edge_option {
-                            if let Some(repo) = &edge.node {
-                                let date_str = match &repo.created_at {
-                                    Some(date) => date.date_naive().to_string(),
-                                    None => {
-                                        continue;
-                                    }
-                                };
-
-                                let name_str = match &repo.name {
-                                    Some(name) => format!("Name: {name},"),
-                                    None => String::new(),
-                                };
-
-                                let desc_str = match &repo.description {
-                                    Some(desc) if desc.len() > 300 => {
-                                        let truncated_desc = desc
-                                            .chars()
-                                            .take(180)
-                                            .chain(desc.chars().skip(desc.chars().count() - 120))
-                                            .collect::<String>();
-
-                                        format!("Description: {truncated_desc}")
-                                    }
-                                    Some(desc) => format!("Description: {desc},"),
-                                    None => String::new(),
-                                };
-
-                                let url_str = match &repo.url {
-                                    Some(url) => format!("Url: {url}"),
-                                    None => String::new(),
-                                };
-
-                                let stars_str = match &repo.stargazers {
-                                    Some(sg) => format!("Stars: {},", sg.total_count.unwrap_or(0)),
-                                    None => String::new(),
-                                };
-
-                                let forks_str = match &repo.fork_count {
-                                    Some(fork_count) => format!("Forks: {fork_count}"),
-                                    None => String::new(),
-                                };
-
-                                out.push_str(
-                                    &format!(
-                                        "{name_str} {desc_str} {url_str} Created At: {date_str} {stars_str} {forks_str}\n"
-                                    )
-                                );
+    let query =
+        r#"
+        query($q: String!, $first: Int!, $after: String) {
+            search(query: $q, type: REPOSITORY, first: $first, after: $after) {
+                repositoryCount
+                edges {
+                    node {
+                        ... on Repository {
+                            name
+                            description
+                            url
+                            createdAt
+                            stargazers {
+                                totalCount
                             }
+                            forkCount
                         }
                     }
                 }
-                if let Some(page_info) = &search.page_info {
-                    if page_info.has_next_page.unwrap_or(false) {
-                        cursor = page_info.end_cursor.clone();
-                    } else {
-                        break;
-                    }
+                pageInfo {
+                    endCursor
+                    hasNextPage
                 }
             }
-        };
-    }
-
-    Ok(out)
+        }
+        "#;
+
+    let response: Payload = GraphqlRequest::new(query)
+        .var("q", search_query)
+        .var("first", page_size(limit))
+        .var_opt("after", cursor)
+        .send(creds).await?;
+    let search = response.data
+        .and_then(|d| d.search)
+        .ok_or_else(|| anyhow::Error::msg("Missing search in the response"))?;
+
+    let items = search.edges
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|edge| edge.and_then(|e| e.node))
+        .filter_map(|repo| {
+            Some(RepoSearchItem {
+                name: repo.name?,
+                description: repo.description,
+                url: repo.url?,
+                created_at: repo.created_at?,
+                stars: repo.stargazers.and_then(|s| s.total_count).unwrap_or(0),
+                forks: repo.fork_count.unwrap_or(0),
+            })
+        })
+        .collect::<Vec<RepoSearchItem>>();
+
+    Ok(SearchPage {
+        total_count: search.repository_count.unwrap_or(items.len() as u64),
+        end_cursor: search.page_info.as_ref().and_then(|p| p.end_cursor.clone()),
+        has_next_page: search.page_info.and_then(|p| p.has_next_page).unwrap_or(false),
+        items,
+    })
 }
 
-pub async fn search_discussions_integrated(
+pub async fn search_discussions(
     search_query: &str,
-    target_person: &Option<String>
-) -> anyhow::Result<(String, Vec<GitMemory>)> {
+    limit: u16,
+    cursor: Option<&str>,
+    creds: &GithubCredentials
+) -> anyhow::Result<SearchPage<DiscussionSearchItem>> {
     #[derive(Debug, Deserialize)]
     struct DiscussionRoot {
         data: Option<Data>,
@@ -969,7 +1084,11 @@ pub async fn search_discussions_integrated(
 
     #[derive(Debug, Deserialize)]
     struct Search {
+        #[serde(rename = "discussionCount")]
+        discussion_count: Option<u64>,
         edges: Option<Vec<Option<Edge>>>,
+        #[serde(rename = "pageInfo")]
+        page_info: Option<GqlPageInfo>,
     }
 
     #[derive(Debug, Deserialize)]
@@ -981,8 +1100,7 @@ pub async fn search_discussions_integrated(
     struct Discussion {
         title: Option<String>,
         url: Option<String>,
-        html_url: Option<String>,
-        author: Option<Author>,
+        author: Option<GqlLogin>,
         body: Option<String>,
         comments: Option<Comments>,
         #[serde(rename = "createdAt")]
@@ -1003,157 +1121,226 @@ pub async fn search_discussions_integrated(
 
     #[derive(Debug, Deserialize)]
     struct CommentNode {
-        author: Option<Author>,
+        author: Option<GqlLogin>,
         body: Option<String>,
     }
 
-    #[derive(Debug, Deserialize)]
-    struct Author {
-        login: Option<String>,
-    }
-
-    let query = format!(
+    let query =
         r#"
-        query {{
-            search(query: "{search_query}", type: DISCUSSION, first: 100) {{
-                edges {{
-                    node {{
-                        ... on Discussion {{
+        query($q: String!, $first: Int!, $after: String) {
+            search(query: $q, type: DISCUSSION, first: $first, after: $after) {
+                discussionCount
+                edges {
+                    node {
+                        ... on Discussion {
                             title
                             url
-                            html_url
                             body
-                            author {{
+                            author {
                                 login
-                            }}
+                            }
                             createdAt
                             upvoteCount
-                            comments (first: 100) {{
-                                edges {{
-                                    node {{
-                                        author {{
+                            comments (first: 100) {
+                                edges {
+                                    node {
+                                        author {
                                             login
-                                        }}
+                                        }
                                         body
-                                    }}
-                                }}
-                            }}
-                        }}
-                    }}
-                }}
-            }}
-        }}
-        "#,
-        search_query = search_query
-    );
-    let mut git_mem_vec = Vec::with_capacity(100);
-    let mut text_out = String::from("DISCUSSIONS \n");
-
-    let octocrab = get_octo(&GithubLogin::Default);
-    let response: DiscussionRoot = octocrab.graphql(&query).await?;
-    let empty_str = "".to_string();
-
-    if
-        let Some(search) = response.data.ok_or_else(||
-            anyhow::Error::msg("Missing data in the response")
-        )?.search
-    {
-        for edge_option in search.edges
-            .ok_or_else(|| anyhow::Error::msg("Missing edges in the response"))?
-            .iter()
-            .filter_map(|e| e.as_ref()) {
-            if let Some(discussion) = &edge_option.node {
-                let date = discussion.created_at.date_naive();
-                let title = discussion.title.as_ref().unwrap_or(&empty_str).to_string();
-                let url = discussion.url.as_ref().unwrap_or(&empty_str).to_string();
-                let source_url = discussion.html_url.as_ref().unwrap_or(&empty_str).to_string();
-                let author_login = discussion.author
-                    .as_ref()
-                    .and_then(|a| a.login.as_ref())
-                    .unwrap_or(&empty_str)
-                    .to_string();
-
-                let upvotes_str = match discussion.upvote_count {
-                    Some(c) if c > 0 => format!("Upvotes: {}", c),
-                    _ => "".to_string(),
-                };
-                let body_text = match discussion.body.as_ref() {
-                    Some(text) => squeeze_fit_remove_quoted(&text, 500, 0.6),
-                    None => "".to_string(),
-                };
-                let mut disuccsion_texts = format!(
-                    "Title: '{}' Url: '{}' Body: '{}' Created At: {} {} Author: {}\n",
-                    title,
-                    url,
-                    body_text,
-                    date,
-                    upvotes_str,
-                    author_login
-                );
-
-                if let Some(comments) = &discussion.comments {
-                    if let Some(ref edges) = comments.edges {
-                        for comment_edge_option in edges.iter().filter_map(|e| e.as_ref()) {
-                            if let Some(comment) = &comment_edge_option.node {
-                                let stripped_comment_text = squeeze_fit_remove_quoted(
-                                    &comment.body.as_ref().unwrap_or(&empty_str),
-                                    300,
-                                    0.6
-                                );
-                                let comment_author = comment.author
-                                    .as_ref()
-                                    .and_then(|a| a.login.as_ref())
-                                    .unwrap_or(&empty_str);
-                                disuccsion_texts.push_str(
-                                    &format!(
-                                        "{comment_author} comments: '{stripped_comment_text}'\n"
-                                    )
-                                );
+                                    }
+                                }
                             }
                         }
                     }
                 }
-                let disuccsion_texts = squeeze_fit_post_texts(&disuccsion_texts, 12_000, 0.4);
-
-                let target_str = match &target_person {
-                    Some(person) => format!("{}'s", person),
-                    None => "key participants'".to_string(),
-                };
-
-                let sys_prompt_1 = &format!(
-                    "Analyze the provided GitHub discussion. Identify the main topic, actions by participants, crucial viewpoints, solutions or consensus reached, and particularly highlight the contributions of specific individuals, especially '{target_str}'. Summarize without being verbose."
-                );
-
-                let usr_prompt_1 = &format!(
-                    "Analyze the content: {disuccsion_texts}. Briefly summarize the central topic, participants' actions, primary viewpoints, and outcomes. Emphasize the role of '{target_str}' in driving the discussion or reaching a resolution. Aim for a succinct summary that is rich in analysis and under 192 tokens."
-                );
-
-                match chat_inner(sys_prompt_1, usr_prompt_1, 256, ChatModel::GPT35Turbo16K).await {
-                    Ok(r) => {
-                        text_out.push_str(&format!("{} {}", url, r));
-                        git_mem_vec.push(GitMemory {
-                            memory_type: MemoryType::Discussion,
-                            name: author_login,
-                            tag_line: title,
-                            source_url: source_url,
-                            payload: r,
-                        });
-                    }
-
-                    Err(_e) => log::error!("Error generating discussion summary #{}: {}", url, _e),
+                pageInfo {
+                    endCursor
+                    hasNextPage
                 }
             }
         }
+        "#;
+
+    let response: DiscussionRoot = GraphqlRequest::new(query)
+        .var("q", search_query)
+        .var("first", page_size(limit))
+        .var_opt("after", cursor)
+        .send(creds).await?;
+    let search = response.data
+        .ok_or_else(|| anyhow::Error::msg("Missing data in the response"))?
+        .search.ok_or_else(|| anyhow::Error::msg("Missing search in the response"))?;
+
+    let items = search.edges
+        .ok_or_else(|| anyhow::Error::msg("Missing edges in the response"))?
+        .into_iter()
+        .filter_map(|edge| edge.and_then(|e| e.node))
+        .map(|discussion| DiscussionSearchItem {
+            title: discussion.title.unwrap_or_default(),
+            url: discussion.url.unwrap_or_default(),
+            author: discussion.author.and_then(|a| a.login),
+            body: discussion.body.unwrap_or_default(),
+            created_at: discussion.created_at,
+            upvotes: discussion.upvote_count.unwrap_or(0),
+            comments: discussion.comments
+                .and_then(|c| c.edges)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|edge| edge.and_then(|e| e.node))
+                .map(|comment| DiscussionComment {
+                    author: comment.author.and_then(|a| a.login),
+                    body: comment.body.unwrap_or_default(),
+                })
+                .collect(),
+        })
+        .collect::<Vec<DiscussionSearchItem>>();
+
+    Ok(SearchPage {
+        total_count: search.discussion_count.unwrap_or(items.len() as u64),
+        end_cursor: search.page_info.as_ref().and_then(|p| p.end_cursor.clone()),
+        has_next_page: search.page_info.and_then(|p| p.has_next_page).unwrap_or(false),
+        items,
+    })
+}
+
+/// Every discussion matching `search_query`, a page of 100 at a time. Like the REST searches, more
+/// matches than search can return is an error.
+pub async fn search_all_discussions(
+    search_query: &str,
+    creds: &GithubCredentials
+) -> anyhow::Result<Vec<DiscussionSearchItem>> {
+    let mut out = Vec::<DiscussionSearchItem>::new();
+    let mut cursor = None::<String>;
+
+    loop {
+        let page = search_discussions(search_query, 100, cursor.as_deref(), creds).await?;
+        if page.total_count > SEARCH_RESULT_LIMIT {
+            return Err(
+                anyhow::anyhow!(
+                    "{} discussions match `{}`, more than the {} search can return; use a shorter window",
+                    page.total_count,
+                    search_query,
+                    SEARCH_RESULT_LIMIT
+                )
+            );
+        }
+        out.extend(page.items);
+        match page.end_cursor {
+            Some(end_cursor) if page.has_next_page => {
+                cursor = Some(end_cursor);
+            }
+            _ => {
+                break;
+            }
+        }
     }
 
+    Ok(out)
+}
+
+/// Summarizes each discussion's contribution with the model, from `target_person`'s point of view
+/// when one is given. A discussion that fails is left out and counted as failed in the session's
+/// progress.
+pub async fn summarize_discussions(
+    discussions: &[DiscussionSearchItem],
+    target_person: &Option<String>,
+    project_summary: &str,
+    llm: &LlmSession
+) -> Vec<GitMemory> {
+    let mut git_mem_vec = Vec::with_capacity(discussions.len());
+    let project_context = project_context(project_summary);
+
+    for discussion in discussions {
+        let upvotes_str = match discussion.upvotes {
+            c if c > 0 => format!("Upvotes: {}", c),
+            _ => "".to_string(),
+        };
+        let body_text = squeeze_fit_remove_quoted(&discussion.body, 500, 0.6);
+        let author_login = discussion.author.clone().unwrap_or_default();
+        let mut disuccsion_texts = format!(
+            "Title: '{}' Url: '{}' Body: '{}' Created At: {} {} Author: {}\n",
+            discussion.title,
+            discussion.url,
+            body_text,
+            discussion.created_at.date_naive(),
+            upvotes_str,
+            author_login
+        );
+
+        for comment in &discussion.comments {
+            let stripped_comment_text = squeeze_fit_remove_quoted(&comment.body, 300, 0.6);
+            let comment_author = comment.author.as_deref().unwrap_or_default();
+            disuccsion_texts.push_str(
+                &format!("{comment_author} comments: '{stripped_comment_text}'\n")
+            );
+        }
+        let disuccsion_texts = squeeze_fit_post_texts(&disuccsion_texts, 12_000, 0.4);
+
+        let target_str = match &target_person {
+            Some(person) => format!("{}'s", person),
+            None => "key participants'".to_string(),
+        };
+
+        let (sys_prompt_1, usr_prompt_1) = render_prompt(
+            PromptName::Discussion,
+            &[
+                ("project_context", &project_context),
+                ("target", &target_str),
+                ("discussion_text", &disuccsion_texts),
+            ]
+        );
+
+        match llm.chat(Stage::Discussion, &discussion.url, &sys_prompt_1, &usr_prompt_1).await {
+            Ok(r) => {
+                llm.progress().summarized(1);
+                git_mem_vec.push(GitMemory {
+                    memory_type: MemoryType::Discussion,
+                    name: author_login,
+                    tag_line: discussion.title.clone(),
+                    source_url: discussion.url.clone(),
+                    payload: r,
+                    date: Some(discussion.created_at),
+                });
+            }
+
+            Err(_e) => {
+                log::error!("Error generating discussion summary #{}: {}", discussion.url, _e);
+                llm.progress().failed(1);
+            }
+        }
+    }
+
+    git_mem_vec
+}
+
+pub async fn search_discussions_integrated(
+    search_query: &str,
+    target_person: &Option<String>,
+    project_summary: &str,
+    llm: &LlmSession,
+    creds: &GithubCredentials
+) -> anyhow::Result<(String, Vec<GitMemory>)> {
+    let page = search_discussions(search_query, 100, None, creds).await?;
+    let git_mem_vec = summarize_discussions(&page.items, target_person, project_summary, llm).await;
+
     if git_mem_vec.is_empty() {
-        Err(anyhow::Error::msg("No results found."))
-    } else {
-        Ok((text_out, git_mem_vec))
+        return Err(anyhow::Error::msg("No results found."));
+    }
+
+    let mut text_out = String::from("DISCUSSIONS \n");
+    for mem in &git_mem_vec {
+        text_out.push_str(&format!("{} {}", mem.source_url, mem.payload));
     }
+
+    Ok((text_out, git_mem_vec))
 }
 
-pub async fn search_users(search_query: &str) -> anyhow::Result<String> {
+pub async fn search_users(
+    search_query: &str,
+    limit: u16,
+    cursor: Option<&str>,
+    creds: &GithubCredentials
+) -> anyhow::Result<SearchPage<UserProfile>> {
     #[derive(Debug, Deserialize)]
     struct User {
         name: Option<String>,
@@ -1176,7 +1363,11 @@ pub async fn search_users(search_query: &str) -> anyhow::Result<String> {
 
     #[derive(Debug, Deserialize)]
     struct UserEdge {
+        #[serde(rename = "userCount")]
+        user_count: Option<u64>,
         edges: Option<Vec<Option<UserNode>>>,
+        #[serde(rename = "pageInfo")]
+        page_info: Option<GqlPageInfo>,
     }
 
     #[derive(Debug, Deserialize)]
@@ -1189,16 +1380,14 @@ pub async fn search_users(search_query: &str) -> anyhow::Result<String> {
         data: Option<UserSearch>,
     }
 
-    let mut out = String::from("USERS: \n");
-    let octocrab = get_octo(&GithubLogin::Default);
-
-    let query = format!(
+    let query =
         r#"
-        query {{
-            search(query: "{search_query}", type: USER, first: 100) {{
-                edges {{
-                    node {{
-                        ... on User {{
+        query($q: String!, $first: Int!, $after: String) {
+            search(query: $q, type: USER, first: $first, after: $after) {
+                userCount
+                edges {
+                    node {
+                        ... on User {
                             name
                             login
                             url
@@ -1208,80 +1397,93 @@ pub async fn search_users(search_query: &str) -> anyhow::Result<String> {
                             location
                             createdAt
                             email
-                        }}
-                    }}
-                }}
-            }}
-        }}
-        "#,
-        search_query = search_query
-    );
-
-    let response: UserRoot = octocrab.graphql(&query).await?;
-
-    if let Some(search) = &response.data {
-        if let Some(edges) = &search.search {
-            for edge_option in edges.edges.as_ref().unwrap_or(&vec![]) {
-                if let Some(edge) = edge_option {
-                    if let Some(user) = &edge.node {
-                        let login_str = match &user.login {
-                            Some(login) => format!("Login: {},", login),
-                            None => {
-                                continue;
-                            }
-                        };
-                        let name_str = match &user.name {
-                            Some(name) => format!("Name: {},", name),
-                            None => String::new(),
-                        };
-
-                        let url_str = match &user.url {
-                            Some(url) => format!("Url: {},", url),
-                            None => String::new(),
-                        };
-
-                        let twitter_str = match &user.twitter_username {
-                            Some(twitter) => format!("Twitter: {},", twitter),
-                            None => String::new(),
-                        };
-
-                        let bio_str = match &user.bio {
-                            Some(bio) => format!("Bio: {},", bio),
-                            None => String::new(),
-                        };
-
-                        let company_str = match &user.company {
-                            Some(company) => format!("Company: {},", company),
-                            None => String::new(),
-                        };
-
-                        let location_str = match &user.location {
-                            Some(location) => format!("Location: {},", location),
-                            None => String::new(),
-                        };
-
-                        let date_str = match &user.created_at {
-                            Some(date) => {
-                                format!("Created At: {},", date.date_naive().to_string())
-                            }
-                            None => String::new(),
-                        };
-
-                        let email_str = match &user.email {
-                            Some(email) => format!("Email: {}", email),
-                            None => String::new(),
-                        };
-
-                        out.push_str(
-                            &format!(
-                                "{name_str} {login_str} {url_str} {twitter_str} {bio_str} {company_str} {location_str} {date_str} {email_str}\n"
-                            )
-                        );
+                        }
                     }
                 }
+                pageInfo {
+                    endCursor
+                    hasNextPage
+                }
             }
         }
+        "#;
+
+    let response: UserRoot = GraphqlRequest::new(query)
+        .var("q", search_query)
+        .var("first", page_size(limit))
+        .var_opt("after", cursor)
+        .send(creds).await?;
+    let search = response.data
+        .and_then(|d| d.search)
+        .ok_or_else(|| anyhow::Error::msg("Missing search in the response"))?;
+
+    let items = search.edges
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|edge| edge.and_then(|e| e.node))
+        .filter_map(|user| {
+            Some(UserProfile {
+                login: user.login?,
+                name: user.name,
+                url: user.url,
+                twitter_username: user.twitter_username,
+                bio: user.bio,
+                company: user.company,
+                location: user.location,
+                created_at: user.created_at,
+                email: user.email,
+            })
+        })
+        .collect::<Vec<UserProfile>>();
+
+    Ok(SearchPage {
+        total_count: search.user_count.unwrap_or(items.len() as u64),
+        end_cursor: search.page_info.as_ref().and_then(|p| p.end_cursor.clone()),
+        has_next_page: search.page_info.and_then(|p| p.has_next_page).unwrap_or(false),
+        items,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q_of(route: &str) -> String {
+        let q = route.split_once("?q=").unwrap().1.split('&').next().unwrap();
+        urlencoding::decode(q).unwrap().into_owned()
     }
 
-    Ok(out)
+    #[test]
+    fn search_route_encodes_the_whole_query() {
+        let query = "repo:a/b is:issue involves:x&per_page=1#frag updated:2024-01-01..2024-01-07";
+        let route = search_route("issues", query, "sort=updated&order=desc&per_page=100", 2);
+
+        assert!(route.starts_with("search/issues?q="));
+        assert!(route.ends_with("&sort=updated&order=desc&per_page=100&page=2"));
+        assert!(!route.contains(' ') && !route.contains('#'));
+        assert_eq!(route.matches("per_page=").count(), 1);
+        assert_eq!(q_of(&route), query);
+    }
+
+    #[test]
+    fn search_route_keeps_quotes_and_plus_signs_in_q() {
+        let query = r#"user:"a+b" language:c++"#;
+        let route = search_route("repositories", query, "per_page=30", 1);
+
+        assert!(!route.contains('"') && !route.contains('+'));
+        assert_eq!(q_of(&route), query);
+    }
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn search_range_ends_a_second_before_until() {
+        let since = at("2024-01-01T00:00:00Z");
+        let until = at("2024-01-08T00:00:00Z");
+        let window = ReportWindow::between(since, until);
+
+        assert_eq!(window.search_range(), "2024-01-01T00:00:00Z..2024-01-07T23:59:59Z");
+    }
 }
@@ -0,0 +1,126 @@
+//! Where GitHub lives. github.com by default; a GitHub Enterprise Server instance, or a local mock
+//! server, is selected with `GITHUB_API_URL` (such as `https://ghe.example.com/api/v3`). The
+//! GraphQL endpoint and the web host are derived from it unless `GITHUB_GRAPHQL_URL` and
+//! `GITHUB_SERVER_URL` are set too. The names are the ones GitHub Actions uses.
+
+use std::sync::OnceLock;
+
+const GITHUB_COM_API: &str = "https://api.github.com";
+
+#[derive(Debug, Clone)]
+pub struct GithubHost {
+    api_url: String,
+    graphql_url: String,
+    web_url: String,
+}
+
+impl GithubHost {
+    /// The host configured for this deployment, read from the environment once.
+    pub fn current() -> &'static GithubHost {
+        static HOST: OnceLock<GithubHost> = OnceLock::new();
+        HOST.get_or_init(GithubHost::from_env)
+    }
+
+    pub fn from_env() -> GithubHost {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        GithubHost::new(
+            var("GITHUB_API_URL").as_deref().unwrap_or(GITHUB_COM_API),
+            var("GITHUB_GRAPHQL_URL").as_deref(),
+            var("GITHUB_SERVER_URL").as_deref()
+        )
+    }
+
+    /// An Enterprise Server API url ends in `/api/v3`, next to `/api/graphql` and the web root;
+    /// any other url is taken to serve all three itself, as a mock server would.
+    pub fn new(api_url: &str, graphql_url: Option<&str>, web_url: Option<&str>) -> GithubHost {
+        let api_url = api_url.trim().trim_end_matches('/').to_string();
+
+        let (default_graphql, default_web) = match api_url.strip_suffix("/api/v3") {
+            _ if api_url == GITHUB_COM_API => {
+                (format!("{GITHUB_COM_API}/graphql"), String::from("https://github.com"))
+            }
+            Some(root) => (format!("{root}/api/graphql"), root.to_string()),
+            None => (format!("{api_url}/graphql"), api_url.clone()),
+        };
+
+        GithubHost {
+            graphql_url: graphql_url
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .unwrap_or(default_graphql),
+            web_url: web_url
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .unwrap_or(default_web),
+            api_url,
+        }
+    }
+
+    pub fn is_github_com(&self) -> bool {
+        self.api_url == GITHUB_COM_API
+    }
+
+    /// The REST API root, without a trailing slash.
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    pub fn graphql_url(&self) -> &str {
+        &self.graphql_url
+    }
+
+    /// The full url of a REST route such as `repos/owner/name`.
+    pub fn api(&self, route: &str) -> String {
+        format!("{}/{}", self.api_url, route.trim_start_matches('/'))
+    }
+
+    /// The full url of a web page such as `owner/name`.
+    pub fn web(&self, path: &str) -> String {
+        format!("{}/{}", self.web_url, path.trim_start_matches('/'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn github_com_urls() {
+        let host = GithubHost::new("https://api.github.com/", None, None);
+
+        assert!(host.is_github_com());
+        assert_eq!(host.api("/repos/a/b"), "https://api.github.com/repos/a/b");
+        assert_eq!(host.graphql_url(), "https://api.github.com/graphql");
+        assert_eq!(host.web("a/b"), "https://github.com/a/b");
+    }
+
+    #[test]
+    fn enterprise_server_urls() {
+        let host = GithubHost::new("https://ghe.example.com/api/v3", None, None);
+
+        assert!(!host.is_github_com());
+        assert_eq!(host.api_url(), "https://ghe.example.com/api/v3");
+        assert_eq!(host.api("repos/a/b"), "https://ghe.example.com/api/v3/repos/a/b");
+        assert_eq!(host.graphql_url(), "https://ghe.example.com/api/graphql");
+        assert_eq!(host.web("/a/b"), "https://ghe.example.com/a/b");
+    }
+
+    #[test]
+    fn mock_server_serves_everything() {
+        let host = GithubHost::new("http://127.0.0.1:8080", None, None);
+
+        assert_eq!(host.api("repos/a/b"), "http://127.0.0.1:8080/repos/a/b");
+        assert_eq!(host.graphql_url(), "http://127.0.0.1:8080/graphql");
+        assert_eq!(host.web("a/b"), "http://127.0.0.1:8080/a/b");
+    }
+
+    #[test]
+    fn explicit_urls_win() {
+        let host = GithubHost::new(
+            "https://ghe.example.com/api/v3",
+            Some("https://graphql.example.com/ "),
+            Some("https://web.example.com/")
+        );
+
+        assert_eq!(host.graphql_url(), "https://graphql.example.com");
+        assert_eq!(host.web("a/b"), "https://web.example.com/a/b");
+    }
+}
@@ -0,0 +1,107 @@
+use crate::credentials::GithubCredentials;
+use crate::github_host::GithubHost;
+use crate::platform::github_octo;
+use serde::de::DeserializeOwned;
+use serde_json::{ json, Map, Value };
+
+/// A GitHub GraphQL request: a fixed query text plus JSON variables. User input only ever goes
+/// into `variables`, so quotes or braces in a search string can't change the query itself.
+#[derive(Debug, Clone)]
+pub struct GraphqlRequest {
+    query: String,
+    variables: Map<String, Value>,
+}
+
+impl GraphqlRequest {
+    pub fn new(query: &str) -> Self {
+        GraphqlRequest {
+            query: query.to_string(),
+            variables: Map::new(),
+        }
+    }
+
+    pub fn var(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.variables.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Sets the variable only when there is a value, leaving nullable variables unset otherwise.
+    pub fn var_opt(self, name: &str, value: Option<impl Into<Value>>) -> Self {
+        match value {
+            Some(value) => self.var(name, value),
+            None => self,
+        }
+    }
+
+    /// The JSON body POSTed to the GraphQL endpoint.
+    pub fn body(&self) -> Value {
+        json!({
+            "query": self.query,
+            "variables": self.variables,
+        })
+    }
+
+    /// Sends the request and deserializes the whole response, `data` included. A response that
+    /// carries GraphQL `errors` and no `data` is turned into an error.
+    pub async fn send<T: DeserializeOwned>(&self, creds: &GithubCredentials) -> anyhow::Result<T> {
+        let octocrab = github_octo(creds).await?;
+        // an absolute url, since Enterprise Server keeps GraphQL outside the REST root
+        let response: Value = octocrab
+            .post(GithubHost::current().graphql_url(), Some(&self.body())).await?;
+
+        if let Some(errors) = response.get("errors").and_then(|e| e.as_array()) {
+            let messages = errors
+                .iter()
+                .filter_map(|e| e.get("message").and_then(|m| m.as_str()))
+                .collect::<Vec<&str>>()
+                .join("; ");
+            match response.get("data") {
+                None | Some(Value::Null) => {
+                    return Err(anyhow::anyhow!("GraphQL error: {}", messages));
+                }
+                Some(_) => log::warn!("GraphQL returned partial data: {}", messages),
+            }
+        }
+
+        Ok(serde_json::from_value::<T>(response)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUERY: &str =
+        "query($q: String!) { search(query: $q, type: ISSUE, first: 10) { issueCount } }";
+
+    #[test]
+    fn body_keeps_hostile_input_in_variables() {
+        let hostile = r#"repo:a/b "} injected { search(query: "x") { issueCount } } {"#;
+        let body = GraphqlRequest::new(QUERY).var("q", hostile).body();
+
+        assert_eq!(body["query"], QUERY);
+        assert_eq!(body["variables"]["q"], hostile);
+        assert_eq!(body.as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn body_round_trips_through_json_text() {
+        let hostile = "quote \" brace } backslash \\ newline \n";
+        let body = GraphqlRequest::new(QUERY).var("q", hostile).body();
+        let sent: Value = serde_json::from_str(&body.to_string()).unwrap();
+
+        assert_eq!(sent["query"], QUERY);
+        assert_eq!(sent["variables"]["q"], hostile);
+    }
+
+    #[test]
+    fn var_opt_leaves_missing_variables_unset() {
+        let body = GraphqlRequest::new(QUERY)
+            .var("first", 10)
+            .var_opt("after", None::<String>)
+            .body();
+
+        assert_eq!(body["variables"]["first"], 10);
+        assert!(body["variables"].get("after").is_none());
+    }
+}
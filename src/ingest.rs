@@ -0,0 +1,172 @@
+//! The daily ingest: fetches only the commits, issues and discussions that saw activity since the
+//! repo's checkpoint, summarizes each one and stores the summaries with `memory`. Reports built
+//! from memory then only need the correlation step.
+//!
+//! The checkpoint moves to the end of the ingested window once the window went through in full:
+//! every search page fetched and every item summarized. A run that failed, left a summary out or
+//! ran out of budget is picked up again by the next one. A dry run stores nothing and leaves the
+//! checkpoint where it was.
+
+use crate::credentials::GithubCredentials;
+use crate::data_analyzers::{ is_valid_owner_repo, process_commits, process_issues };
+use crate::github_data_fetchers::{
+    get_commits_in_range_search,
+    get_issues_in_range,
+    search_all_discussions,
+    summarize_discussions,
+    GitMemory,
+    ReportWindow,
+};
+use crate::llm::{ LlmConfig, LlmSession };
+use crate::memory::{ checkpoint, remember, remember_project, set_checkpoint };
+use crate::reports::closed_issue_lines;
+use chrono::{ DateTime, Duration, Utc };
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IngestRun {
+    pub owner_repo: String,
+    pub window: ReportWindow,
+    pub commits: usize,
+    pub issues: usize,
+    pub discussions: usize,
+    /// Summaries stored; an issue gets one per participant. None in a dry run.
+    pub stored: usize,
+    /// Commits, issues and discussions whose summary failed.
+    pub failed: usize,
+    /// Whether the checkpoint moved to the end of the window.
+    pub complete: bool,
+}
+
+/// Ingests what happened in `owner_repo` since its checkpoint, or over the `first_days` before
+/// `now` when it has none yet.
+pub async fn ingest_since_checkpoint(
+    owner_repo: &str,
+    first_days: u16,
+    now: DateTime<Utc>,
+    creds: &GithubCredentials,
+    llm: &LlmSession
+) -> anyhow::Result<IngestRun> {
+    let creds = &creds.for_owner(owner_repo.split('/').next().unwrap_or(owner_repo));
+    let since = checkpoint(owner_repo).unwrap_or(now - Duration::days(first_days as i64));
+    let window = ReportWindow::between(since, now);
+    let progress = llm.progress();
+
+    progress.phase("checking repo");
+    let (_, project_summary, contributors_set) = is_valid_owner_repo(owner_repo, llm, creds).await?;
+    let dry_run = llm.is_dry_run();
+    if !dry_run {
+        remember_project(owner_repo, &project_summary);
+    }
+
+    let mut memories = Vec::<GitMemory>::new();
+
+    progress.phase("fetching commits");
+    let commits = match get_commits_in_range_search(owner_repo, None, &window, creds).await {
+        Ok((_, commits)) => commits,
+        Err(_e) => {
+            return Err(anyhow::anyhow!("Failed to fetch the commits of {}: {}", owner_repo, _e));
+        }
+    };
+    progress.fetched(commits.len());
+    progress.phase("summarizing commits");
+    let commit_count = commits.len();
+    memories.extend(process_commits(commits, &project_summary, llm, creds).await);
+
+    progress.phase("fetching issues");
+    let issues = match get_issues_in_range(owner_repo, None, &window, creds).await {
+        Ok((_, issues)) => issues,
+        Err(_e) => {
+            return Err(anyhow::anyhow!("Failed to fetch the issues of {}: {}", owner_repo, _e));
+        }
+    };
+    progress.fetched(issues.len());
+    progress.phase("summarizing issues");
+    let issue_count = issues.len();
+    let closed_issues = closed_issue_lines(&issues, &window);
+    memories.extend(
+        process_issues(issues, None, contributors_set, &project_summary, llm, creds).await
+            .unwrap_or_default()
+    );
+
+    progress.phase("fetching discussions");
+    let query = format!("repo:{} updated:{}", owner_repo, window.search_range());
+    let discussions = match search_all_discussions(&query, creds).await {
+        Ok(discussions) => discussions,
+        Err(_e) => {
+            return Err(anyhow::anyhow!("Failed to fetch the discussions of {}: {}", owner_repo, _e));
+        }
+    };
+    progress.fetched(discussions.len());
+    progress.phase("summarizing discussions");
+    let discussion_memories = summarize_discussions(&discussions, &None, &project_summary, llm).await;
+    // a discussion found by its comments may have been opened long before the window
+    memories.extend(
+        discussion_memories.into_iter().map(|memory| GitMemory {
+            date: memory.date.map(|date| date.max(window.since)),
+            ..memory
+        })
+    );
+
+    let stored = if dry_run { 0 } else { memories.len() };
+    if !dry_run {
+        remember(owner_repo, memories, closed_issues, now);
+    }
+
+    // a summary left out now would never be stored once the checkpoint passed it
+    let failed = progress.snapshot().failed;
+    if failed > 0 {
+        log::error!("{} summaries of {} failed; its checkpoint stays put", failed, owner_repo);
+    }
+    let complete = !dry_run && !llm.budget_exceeded() && failed == 0;
+    if complete {
+        set_checkpoint(owner_repo, window.until);
+    }
+
+    Ok(IngestRun {
+        owner_repo: owner_repo.to_string(),
+        window,
+        commits: commit_count,
+        issues: issue_count,
+        discussions: discussions.len(),
+        stored,
+        failed,
+        complete,
+    })
+}
+
+/// The repos to ingest on every tick, from the comma separated `INGEST_REPOS`.
+pub fn ingest_repos() -> Vec<String> {
+    std::env
+        ::var("INGEST_REPOS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|repo| repo.trim().to_string())
+        .filter(|repo| repo.contains('/'))
+        .collect()
+}
+
+/// Ingests each of `INGEST_REPOS` whose checkpoint is a day old, with the deployment's
+/// credentials and the LLM settings of the environment.
+pub async fn run_due_ingests(now: DateTime<Utc>) -> Vec<anyhow::Result<IngestRun>> {
+    let creds = GithubCredentials::deployment();
+    let mut runs = Vec::new();
+    for owner_repo in ingest_repos() {
+        // ticks drift by a few seconds; don't let that push a run back a whole tick
+        let due = checkpoint(&owner_repo).map_or(true, |at| {
+            now + Duration::minutes(30) >= at + Duration::days(1)
+        });
+        if !due {
+            continue;
+        }
+        let llm = LlmSession::new(LlmConfig::load(&HashMap::<String, Value>::new()));
+        let run = ingest_since_checkpoint(&owner_repo, 1, now, &creds, &llm).await;
+        if let Err(_e) = &run {
+            log::error!("Ingest of {} failed: {}", owner_repo, _e);
+        }
+        runs.push(run);
+    }
+    runs
+}
@@ -0,0 +1,159 @@
+//! Reports run as background jobs. Starting one answers at once with a job id; the report then
+//! runs after the response is sent, and its progress and finally its result are kept in the store
+//! under that id for the caller to poll.
+//!
+//! The caller's GitHub token is held in memory for as long as the job runs and is never stored.
+//! Job ids are random, so a job's result is only visible to whoever started it or was handed the
+//! id.
+
+use crate::credentials::GithubCredentials;
+use crate::github_data_fetchers::ReportWindow;
+use crate::llm::{ LlmConfig, LlmSession };
+use crate::platform::{ store_del, store_get, store_set };
+use crate::progress::{ ProgressSnapshot, ReportProgress };
+use crate::reports::{ run_target, ReportTarget };
+use crate::sections::ReportSection;
+use crate::sinks::Deliverable;
+use crate::utils::new_id;
+use chrono::{ DateTime, Duration, Utc };
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: String,
+    /// `queued`, `running`, `done` or `failed`.
+    pub status: String,
+    pub target: ReportTarget,
+    pub window: ReportWindow,
+    pub progress: ProgressSnapshot,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Deliverable>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+fn job_key(id: &str) -> String {
+    format!("job:{id}")
+}
+
+// written as the report goes, so kept apart from the record
+fn progress_key(id: &str) -> String {
+    format!("job_progress:{id}")
+}
+
+// the native store rewrites its whole file on every write, so progress is written when the phase
+// changes and otherwise at most this often
+const PROGRESS_INTERVAL_SECS: i64 = 2;
+
+/// The job with its latest progress, if there is such a job.
+pub fn load_job(id: &str) -> Option<JobRecord> {
+    let mut record = store_get(&job_key(id)).and_then(|value|
+        serde_json::from_value::<JobRecord>(value).ok()
+    )?;
+    if record.status == "running" {
+        if let Some(Ok(progress)) = store_get(&progress_key(id)).map(serde_json::from_value) {
+            record.progress = progress;
+        }
+    }
+    Some(record)
+}
+
+fn save_job(record: &JobRecord) {
+    match serde_json::to_value(record) {
+        Ok(value) => store_set(&job_key(&record.id), value),
+        Err(_e) => log::error!("Failed to save job {}: {}", record.id, _e),
+    }
+}
+
+/// A queued report, along with what it needs to run.
+pub struct ReportJob {
+    record: JobRecord,
+    creds: GithubCredentials,
+    sections: Vec<ReportSection>,
+    options: HashMap<String, Value>,
+}
+
+impl ReportJob {
+    /// Records the job as queued. `options` holds the LLM settings, as `LlmConfig::load` takes them.
+    pub fn queue(
+        target: ReportTarget,
+        window: ReportWindow,
+        creds: GithubCredentials,
+        sections: Vec<ReportSection>,
+        options: HashMap<String, Value>
+    ) -> Self {
+        let now = Utc::now();
+        let record = JobRecord {
+            id: new_id("job"),
+            status: String::from("queued"),
+            target,
+            window,
+            progress: ProgressSnapshot::default(),
+            result: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        save_job(&record);
+        ReportJob { record, creds, sections, options }
+    }
+
+    pub fn record(&self) -> &JobRecord {
+        &self.record
+    }
+
+    /// Runs the report to the end, recording its progress as it goes and its result or error
+    /// at the end.
+    pub async fn run(self) {
+        let ReportJob { mut record, creds, sections, options } = self;
+        record.status = String::from("running");
+        record.updated_at = Utc::now();
+        save_job(&record);
+
+        let id = record.id.clone();
+        let last_write = Mutex::new((String::new(), None::<DateTime<Utc>>));
+        let progress = ReportProgress::with_listener(move |snapshot| {
+            let now = Utc::now();
+            let mut last = match last_write.lock() {
+                Ok(last) => last,
+                Err(_) => {
+                    return;
+                }
+            };
+            let due =
+                last.0 != snapshot.phase ||
+                last.1.map_or(true, |at| now - at >= Duration::seconds(PROGRESS_INTERVAL_SECS));
+            if !due {
+                return;
+            }
+            *last = (snapshot.phase.clone(), Some(now));
+            if let Ok(value) = serde_json::to_value(snapshot) {
+                store_set(&progress_key(&id), value);
+            }
+        });
+        let llm = LlmSession::new(LlmConfig::load(&options)).with_progress(progress);
+
+        let outcome = run_target(&record.target, &record.window, &creds, &sections, &llm).await;
+
+        record.progress = llm.progress().snapshot();
+        record.updated_at = Utc::now();
+        match outcome {
+            Ok(deliverable) => {
+                record.status = String::from("done");
+                record.result = Some(deliverable);
+            }
+            Err(_e) => {
+                log::error!("Report job {} failed: {}", record.id, _e);
+                record.status = String::from("failed");
+                record.error = Some(_e.to_string());
+            }
+        }
+        save_job(&record);
+        store_del(&progress_key(&record.id));
+    }
+}
@@ -0,0 +1,519 @@
+use crate::llm_json::chat_json;
+use crate::progress::ReportProgress;
+use crate::usage::{ count_tokens, estimate_cost_usd, UsageLedger, UsageReport };
+use async_openai::{
+    types::{
+        ChatCompletionRequestMessage,
+        ChatCompletionRequestSystemMessageArgs,
+        ChatCompletionRequestUserMessageArgs,
+        ChatCompletionResponseFormat,
+        ChatCompletionResponseFormatType,
+        CreateChatCompletionRequestArgs,
+    },
+    Client,
+};
+use log;
+use serde::{ Deserialize, Serialize };
+use serde_json::{ Map, Value };
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Readme,
+    Commit,
+    Issue,
+    Discussion,
+    Correlation,
+    Scraper,
+    ExecutiveSummary,
+    ReleaseNotes,
+    Answer,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 9] = [
+        Stage::Readme,
+        Stage::Commit,
+        Stage::Issue,
+        Stage::Discussion,
+        Stage::Correlation,
+        Stage::Scraper,
+        Stage::ExecutiveSummary,
+        Stage::ReleaseNotes,
+        Stage::Answer,
+    ];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Stage::Readme => "readme",
+            Stage::Commit => "commit",
+            Stage::Issue => "issue",
+            Stage::Discussion => "discussion",
+            Stage::Correlation => "correlation",
+            Stage::Scraper => "scraper",
+            Stage::ExecutiveSummary => "executive_summary",
+            Stage::ReleaseNotes => "release_notes",
+            Stage::Answer => "answer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageSettings {
+    pub model: String,
+    pub fallback_model: Option<String>,
+    pub temperature: f32,
+    pub max_tokens: u16,
+}
+
+impl StageSettings {
+    fn new(model: &str, temperature: f32, max_tokens: u16) -> Self {
+        StageSettings {
+            model: model.to_string(),
+            fallback_model: None,
+            temperature,
+            max_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LlmConfig {
+    stages: HashMap<Stage, StageSettings>,
+    pub budget_usd: Option<f64>,
+    pub dry_run: bool,
+    pub show_prompts: bool,
+}
+
+impl LlmConfig {
+    pub fn with_defaults() -> Self {
+        let stages = Stage::ALL.iter()
+            .map(|stage| {
+                let settings = match stage {
+                    Stage::Readme => StageSettings::new("gpt-3.5-turbo-16k", 0.7, 256),
+                    Stage::Commit => StageSettings::new("gpt-3.5-turbo-16k", 0.7, 128),
+                    Stage::Issue => StageSettings::new("gpt-3.5-turbo-16k", 0.7, 128),
+                    Stage::Discussion => StageSettings::new("gpt-3.5-turbo-16k", 0.7, 256),
+                    Stage::Correlation => StageSettings::new("gpt-3.5-turbo-1106", 1.0, 500),
+                    Stage::Scraper => StageSettings::new("gpt-3.5-turbo-16k", 0.7, 700),
+                    Stage::ExecutiveSummary => StageSettings::new("gpt-3.5-turbo-16k", 0.7, 400),
+                    Stage::ReleaseNotes => StageSettings::new("gpt-3.5-turbo-16k", 0.7, 700),
+                    Stage::Answer => StageSettings::new("gpt-3.5-turbo-16k", 0.3, 500),
+                };
+                (*stage, settings)
+            })
+            .collect::<HashMap<Stage, StageSettings>>();
+
+        LlmConfig {
+            stages,
+            budget_usd: None,
+            dry_run: false,
+            show_prompts: false,
+        }
+    }
+
+    /// Layers the settings, later sources winning: defaults, `LLM_MODEL` style env vars,
+    /// `LLM_<STAGE>_MODEL` style env vars, `model` style query parameters, and finally
+    /// `<stage>_model` style query parameters. Each of `model`, `fallback_model`, `temperature`
+    /// and `max_tokens` can be set this way. The spending cap comes from `budget_usd` or
+    /// `LLM_BUDGET_USD`, and `dry_run=true` / `show_prompts=true` turn on dry-run mode.
+    pub fn load(qry: &HashMap<String, Value>) -> Self {
+        let mut config = LlmConfig::with_defaults();
+
+        config.apply(|field| std::env::var(format!("LLM_{}", field.to_uppercase())).ok(), None);
+        for stage in Stage::ALL.iter() {
+            config.apply(
+                |field| {
+                    std::env::var(
+                        format!("LLM_{}_{}", stage.key().to_uppercase(), field.to_uppercase())
+                    ).ok()
+                },
+                Some(*stage)
+            );
+        }
+
+        let query_value = |name: String| -> Option<String> {
+            match qry.get(&name)? {
+                Value::String(s) => Some(s.to_string()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            }
+        };
+        config.apply(|field| query_value(field.to_string()), None);
+        for stage in Stage::ALL.iter() {
+            config.apply(|field| query_value(format!("{}_{}", stage.key(), field)), Some(*stage));
+        }
+
+        config.budget_usd = query_value("budget_usd".to_string())
+            .or_else(|| std::env::var("LLM_BUDGET_USD").ok())
+            .and_then(|b| b.parse::<f64>().ok());
+        config.dry_run = query_value("dry_run".to_string()).map_or(false, |v| v == "true");
+        config.show_prompts = query_value("show_prompts".to_string()).map_or(false, |v| v == "true");
+
+        config
+    }
+
+    fn apply<F>(&mut self, lookup: F, only: Option<Stage>) where F: Fn(&str) -> Option<String> {
+        let model = lookup("model");
+        let fallback_model = lookup("fallback_model");
+        let temperature = lookup("temperature").and_then(|t| t.parse::<f32>().ok());
+        let max_tokens = lookup("max_tokens").and_then(|t| t.parse::<u16>().ok());
+
+        for (stage, settings) in self.stages.iter_mut() {
+            if only.map_or(false, |s| s != *stage) {
+                continue;
+            }
+            if let Some(model) = &model {
+                settings.model = model.clone();
+            }
+            if let Some(fallback_model) = &fallback_model {
+                settings.fallback_model = Some(fallback_model.clone());
+            }
+            if let Some(temperature) = temperature {
+                settings.temperature = temperature;
+            }
+            if let Some(max_tokens) = max_tokens {
+                settings.max_tokens = max_tokens;
+            }
+        }
+    }
+
+    pub fn settings(&self, stage: Stage) -> StageSettings {
+        self.stages
+            .get(&stage)
+            .cloned()
+            .unwrap_or_else(|| StageSettings::new("gpt-3.5-turbo-16k", 0.7, 256))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelUse {
+    pub stage: Stage,
+    pub label: String,
+    pub model: String,
+    pub fallback: bool,
+}
+
+/// A model call that dry-run mode built the prompts for but did not send. The cost assumes the
+/// reply uses all of `max_tokens`, so it is an upper bound.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedCall {
+    pub stage: Stage,
+    pub label: String,
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub max_tokens: u16,
+    pub estimated_cost_usd: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_prompt: Option<String>,
+}
+
+/// Per-report handle to the model: resolves each stage's settings, falls back to the stage's
+/// fallback model when the primary one errors or returns something unusable, and records which
+/// model produced each piece of the report along with the tokens it spent. It also carries the
+/// report's progress, since every stage of the report already has it at hand.
+#[derive(Debug)]
+pub struct LlmSession {
+    pub config: LlmConfig,
+    models_used: Mutex<Vec<ModelUse>>,
+    usage: UsageLedger,
+    planned: Mutex<Vec<PlannedCall>>,
+    progress: ReportProgress,
+}
+
+impl LlmSession {
+    pub fn new(config: LlmConfig) -> Self {
+        let usage = UsageLedger::new(config.budget_usd);
+        LlmSession {
+            config,
+            models_used: Mutex::new(Vec::new()),
+            usage,
+            planned: Mutex::new(Vec::new()),
+            progress: ReportProgress::new(),
+        }
+    }
+
+    pub fn with_progress(mut self, progress: ReportProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn progress(&self) -> &ReportProgress {
+        &self.progress
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.config.dry_run
+    }
+
+    pub fn planned_calls(&self) -> Vec<PlannedCall> {
+        self.planned
+            .lock()
+            .map(|p| p.clone())
+            .unwrap_or_default()
+    }
+
+    fn plan(&self, stage: Stage, label: &str, system_prompt: &str, user_input: &str) {
+        let settings = self.config.settings(stage);
+        let prompt_tokens = (count_tokens(system_prompt) + count_tokens(user_input)) as u32;
+        let show_prompts = self.config.show_prompts;
+
+        if let Ok(mut planned) = self.planned.lock() {
+            planned.push(PlannedCall {
+                stage,
+                label: label.to_string(),
+                model: settings.model.clone(),
+                prompt_tokens,
+                max_tokens: settings.max_tokens,
+                estimated_cost_usd: estimate_cost_usd(
+                    &settings.model,
+                    prompt_tokens as u64,
+                    settings.max_tokens as u64
+                ),
+                system_prompt: show_prompts.then(|| system_prompt.to_string()),
+                user_prompt: show_prompts.then(|| user_input.to_string()),
+            });
+        }
+    }
+
+    pub fn usage(&self) -> UsageReport {
+        self.usage.snapshot()
+    }
+
+    pub fn budget_exceeded(&self) -> bool {
+        self.usage.budget_exceeded()
+    }
+
+    /// Sets aside the worst case of `calls` attempts at the prompt, each reply using all of
+    /// `max_tokens`, with a retry resending the prompt along with the previous reply.
+    fn reserve(
+        &self,
+        settings: &StageSettings,
+        system_prompt: &str,
+        user_input: &str,
+        calls: u64
+    ) -> anyhow::Result<f64> {
+        if self.config.budget_usd.is_none() {
+            return Ok(0.0);
+        }
+        let prompt_tokens = (count_tokens(system_prompt) + count_tokens(user_input)) as u64;
+        let max_tokens = settings.max_tokens as u64;
+        // 100 covers the hint a retry adds
+        let worst_prompt_tokens = calls * prompt_tokens + (calls - 1) * (max_tokens + 100);
+        self.usage.reserve(&settings.model, worst_prompt_tokens, calls * max_tokens)
+    }
+
+    pub fn models_used(&self) -> Vec<ModelUse> {
+        self.models_used
+            .lock()
+            .map(|m| m.clone())
+            .unwrap_or_default()
+    }
+
+    fn record(&self, stage: Stage, label: &str, model: &str, fallback: bool) {
+        if let Ok(mut models_used) = self.models_used.lock() {
+            models_used.push(ModelUse {
+                stage,
+                label: label.to_string(),
+                model: model.to_string(),
+                fallback,
+            });
+        }
+    }
+
+    fn candidates(&self, stage: Stage) -> Vec<(StageSettings, bool)> {
+        let settings = self.config.settings(stage);
+        let mut out = vec![(settings.clone(), false)];
+        if let Some(fallback_model) = &settings.fallback_model {
+            if fallback_model != &settings.model {
+                let mut fallback = settings.clone();
+                fallback.model = fallback_model.clone();
+                out.push((fallback, true));
+            }
+        }
+        out
+    }
+
+    pub async fn chat(
+        &self,
+        stage: Stage,
+        label: &str,
+        system_prompt: &str,
+        user_input: &str
+    ) -> anyhow::Result<String> {
+        self.chat_with_fallback(stage, label, system_prompt, user_input).await
+    }
+
+    async fn chat_with_fallback(
+        &self,
+        stage: Stage,
+        label: &str,
+        system_prompt: &str,
+        user_input: &str
+    ) -> anyhow::Result<String> {
+        if self.config.dry_run {
+            self.plan(stage, label, system_prompt, user_input);
+            return Ok(format!("[dry run: no {} output for {}]", stage.key(), label));
+        }
+
+        let mut last_error = anyhow::anyhow!("No model configured for {}", stage.key());
+
+        for (settings, fallback) in self.candidates(stage) {
+            let reserved = self.reserve(&settings, system_prompt, user_input, 1)?;
+            let messages = chat_messages(system_prompt, user_input)?;
+            let reply = chat_completion(
+                &settings.model,
+                settings.temperature,
+                settings.max_tokens,
+                messages,
+                false
+            ).await;
+            if let Ok(reply) = &reply {
+                self.usage.record(stage, &settings.model, reply.prompt_tokens, reply.completion_tokens);
+            }
+            self.usage.release(reserved);
+            match reply {
+                Ok(reply) if !reply.text.trim().is_empty() => {
+                    self.record(stage, label, &settings.model, fallback);
+                    return Ok(reply.text);
+                }
+                Ok(_) => {
+                    last_error = anyhow::anyhow!("{} returned an empty reply", settings.model);
+                }
+                Err(e) => {
+                    last_error = e;
+                }
+            }
+            log::error!("{} failed on {} for {}: {}", settings.model, stage.key(), label, last_error);
+        }
+
+        Err(last_error)
+    }
+
+    pub async fn chat_json<F>(
+        &self,
+        stage: Stage,
+        label: &str,
+        system_prompt: &str,
+        user_input: &str,
+        validate: F
+    ) -> anyhow::Result<Map<String, Value>>
+        where F: Fn(&Map<String, Value>) -> anyhow::Result<()>
+    {
+        self.chat_json_with_fallback(stage, label, system_prompt, user_input, validate).await
+    }
+
+    async fn chat_json_with_fallback<F>(
+        &self,
+        stage: Stage,
+        label: &str,
+        system_prompt: &str,
+        user_input: &str,
+        validate: F
+    ) -> anyhow::Result<Map<String, Value>>
+        where F: Fn(&Map<String, Value>) -> anyhow::Result<()>
+    {
+        if self.config.dry_run {
+            self.plan(stage, label, system_prompt, user_input);
+            return Ok(Map::new());
+        }
+
+        let mut last_error = anyhow::anyhow!("No model configured for {}", stage.key());
+
+        for (settings, fallback) in self.candidates(stage) {
+            // chat_json retries once when the reply isn't usable JSON
+            let reserved = self.reserve(&settings, system_prompt, user_input, 2)?;
+            let on_reply = |reply: &ChatReply| {
+                self.usage.record(stage, &settings.model, reply.prompt_tokens, reply.completion_tokens);
+            };
+            let parsed = chat_json(
+                system_prompt,
+                user_input,
+                &settings.model,
+                settings.temperature,
+                settings.max_tokens,
+                &validate,
+                &on_reply
+            ).await;
+            self.usage.release(reserved);
+            match parsed {
+                Ok(parsed) => {
+                    self.record(stage, label, &settings.model, fallback);
+                    return Ok(parsed);
+                }
+                Err(e) => {
+                    log::error!("{} failed on {} for {}: {}", settings.model, stage.key(), label, e);
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+pub fn chat_messages(
+    system_prompt: &str,
+    user_input: &str
+) -> anyhow::Result<Vec<ChatCompletionRequestMessage>> {
+    Ok(
+        vec![
+            ChatCompletionRequestSystemMessageArgs::default().content(system_prompt).build()?.into(),
+            ChatCompletionRequestUserMessageArgs::default().content(user_input).build()?.into()
+        ]
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatReply {
+    pub text: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+pub async fn chat_completion(
+    model: &str,
+    temperature: f32,
+    max_tokens: u16,
+    messages: Vec<ChatCompletionRequestMessage>,
+    json_mode: bool
+) -> anyhow::Result<ChatReply> {
+    let client = Client::new();
+
+    let mut request = CreateChatCompletionRequestArgs::default();
+    request.max_tokens(max_tokens).temperature(temperature).model(model).messages(messages);
+    if json_mode {
+        request.response_format(ChatCompletionResponseFormat {
+            r#type: ChatCompletionResponseFormatType::JsonObject,
+        });
+    }
+    let request = request.build()?;
+
+    let chat = client.chat().create(request.clone()).await?;
+
+    let text = match chat.choices.get(0).and_then(|choice| choice.message.content.clone()) {
+        Some(res) => res,
+        None => {
+            return Err(anyhow::anyhow!("Failed to get reply from OpenAI"));
+        }
+    };
+
+    let (prompt_tokens, completion_tokens) = match &chat.usage {
+        Some(usage) => (usage.prompt_tokens, usage.completion_tokens),
+        // only counted when the API leaves out the usage block
+        None => {
+            let prompt = serde_json::to_string(&request.messages).unwrap_or_default();
+            (count_tokens(&prompt) as u32, count_tokens(&text) as u32)
+        }
+    };
+
+    Ok(ChatReply {
+        text,
+        prompt_tokens,
+        completion_tokens,
+    })
+}
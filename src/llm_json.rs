@@ -0,0 +1,300 @@
+use crate::llm::{ chat_completion, chat_messages, ChatReply };
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessageArgs,
+    ChatCompletionRequestUserMessageArgs,
+};
+use log;
+use serde_json::{ Map, Value };
+
+/// Pulls a JSON object out of a model reply. Tries the reply as-is first, then strips Markdown
+/// code fences, cuts out the outermost `{...}` and finally repairs the usual faults: smart quotes,
+/// trailing commas, raw newlines or stray quotes inside strings, and truncated output.
+pub fn extract_json_object(raw: &str) -> anyhow::Result<Map<String, Value>> {
+    if let Ok(Value::Object(map)) = serde_json::from_str::<Value>(raw.trim()) {
+        return Ok(map);
+    }
+
+    let unfenced = strip_code_fences(raw);
+    let candidate = match outermost_json_object(&unfenced) {
+        Some(c) => c,
+        None => {
+            return Err(anyhow::anyhow!("No JSON object found in model output"));
+        }
+    };
+
+    if let Ok(Value::Object(map)) = serde_json::from_str::<Value>(candidate) {
+        return Ok(map);
+    }
+
+    match serde_json::from_str::<Value>(&repair_json(candidate)) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(_) => Err(anyhow::anyhow!("Model output is not a JSON object")),
+        Err(e) => Err(anyhow::anyhow!("Failed to parse JSON from model output: {}", e)),
+    }
+}
+
+fn strip_code_fences(raw: &str) -> String {
+    raw.lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+// from the first '{' to its matching '}', or to the end of the text if the reply was cut off
+fn outermost_json_object(text: &str) -> Option<&str> {
+    let start = text.find('{')?;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text[start..].char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => {
+                    escaped = false;
+                }
+                '\\' => {
+                    escaped = true;
+                }
+                '"' => {
+                    in_string = false;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+            }
+            '{' => {
+                depth += 1;
+            }
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[start..start + i + 1]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(&text[start..])
+}
+
+fn repair_json(text: &str) -> String {
+    let text = text.replace(['\u{201C}', '\u{201D}'], "\"");
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut out = String::with_capacity(text.len());
+    let mut closers = Vec::<char>::new();
+    let mut in_string = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if in_string {
+            match c {
+                '\\' => {
+                    match chars.get(i + 1) {
+                        Some(next) if "\"\\/bfnrtu".contains(*next) => {
+                            out.push(c);
+                            out.push(*next);
+                        }
+                        // invalid escape such as \' : keep the character, drop the backslash
+                        Some(next) => out.push(*next),
+                        None => {}
+                    }
+                    i += 2;
+                    continue;
+                }
+                '"' => {
+                    // a quote only closes the string if what follows looks like JSON structure
+                    let next_significant = chars[i + 1..].iter().find(|ch| !ch.is_whitespace());
+                    match next_significant {
+                        None | Some(':') | Some(',') | Some('}') | Some(']') => {
+                            in_string = false;
+                            out.push(c);
+                        }
+                        Some(_) => out.push_str("\\\""),
+                    }
+                }
+                '\n' => out.push_str("\\n"),
+                '\r' => {}
+                '\t' => out.push_str("\\t"),
+                _ => out.push(c),
+            }
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '{' => {
+                closers.push('}');
+                out.push(c);
+            }
+            '[' => {
+                closers.push(']');
+                out.push(c);
+            }
+            '}' | ']' => {
+                closers.pop();
+                out.push(c);
+            }
+            ',' => {
+                let next_significant = chars[i + 1..].iter().find(|ch| !ch.is_whitespace());
+                match next_significant {
+                    Some('}') | Some(']') | None => {}
+                    _ => out.push(c),
+                }
+            }
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+
+    if in_string {
+        out.push('"');
+    }
+    while let Some(closer) = closers.pop() {
+        out.push(closer);
+    }
+
+    out
+}
+
+pub fn supports_json_mode(model: &str) -> bool {
+    ["1106", "0125", "gpt-4-turbo", "gpt-4o"].iter().any(|marker| model.contains(marker))
+}
+
+/// Asks the model for a JSON object, in JSON mode when the model supports it. If the reply can't
+/// be extracted or fails `validate`, the model is asked once more with the error as a hint.
+/// `on_reply` sees every completion, including the retry, so usage can be accounted for.
+pub async fn chat_json<F, R>(
+    system_prompt: &str,
+    user_input: &str,
+    model: &str,
+    temperature: f32,
+    max_token: u16,
+    validate: &F,
+    on_reply: &R
+) -> anyhow::Result<Map<String, Value>>
+    where F: Fn(&Map<String, Value>) -> anyhow::Result<()>, R: Fn(&ChatReply)
+{
+    let check = |raw: &str| -> anyhow::Result<Map<String, Value>> {
+        let map = extract_json_object(raw)?;
+        validate(&map)?;
+        Ok(map)
+    };
+    let json_mode = supports_json_mode(model);
+
+    let messages = chat_messages(system_prompt, user_input)?;
+    let first_reply = chat_completion(
+        model,
+        temperature,
+        max_token,
+        messages.clone(),
+        json_mode
+    ).await?;
+    on_reply(&first_reply);
+    let first_reply = first_reply.text;
+    let error = match check(&first_reply) {
+        Ok(map) => {
+            return Ok(map);
+        }
+        Err(e) => e,
+    };
+
+    log::warn!("Unusable JSON from {}, retrying with a hint: {}", model, error);
+    let hint = format!(
+        "Your previous reply could not be used: {error}. Reply again with only a single, complete JSON object that fixes this, without code fences or any other text."
+    );
+    let mut retry_messages = messages;
+    retry_messages.push(
+        ChatCompletionRequestAssistantMessageArgs::default().content(first_reply).build()?.into()
+    );
+    retry_messages.push(ChatCompletionRequestUserMessageArgs::default().content(hint).build()?.into());
+
+    let second_reply = chat_completion(
+        model,
+        temperature,
+        max_token,
+        retry_messages,
+        json_mode
+    ).await?;
+    on_reply(&second_reply);
+
+    check(&second_reply.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(raw: &str, key: &str) -> String {
+        let map = extract_json_object(raw).unwrap();
+        map[key].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn plain_object() {
+        assert_eq!(field(r#"{"a": "1"}"#, "a"), "1");
+    }
+
+    #[test]
+    fn code_fences() {
+        let raw = "```json\n{\"a\": \"fenced\"}\n```";
+        assert_eq!(strip_code_fences(raw), "{\"a\": \"fenced\"}");
+        assert_eq!(field(raw, "a"), "fenced");
+    }
+
+    #[test]
+    fn prose_before_and_after() {
+        let raw = "Sure, here it is:\n{\"a\": \"1\"}\nHope this helps {not json}.";
+        assert_eq!(field(raw, "a"), "1");
+    }
+
+    #[test]
+    fn braces_inside_escaped_quote_strings() {
+        let raw = r#"Result: {"a": "say \"}\" and {", "b": "x"} done"#;
+        assert_eq!(
+            outermost_json_object(raw),
+            Some(r#"{"a": "say \"}\" and {", "b": "x"}"#)
+        );
+        assert_eq!(field(raw, "a"), "say \"}\" and {");
+        assert_eq!(field(raw, "b"), "x");
+    }
+
+    #[test]
+    fn trailing_commas() {
+        let map = extract_json_object(r#"{"a": "1", "b": [1, 2,],}"#).unwrap();
+        assert_eq!(map["a"], "1");
+        assert_eq!(map["b"], serde_json::json!([1, 2]));
+    }
+
+    #[test]
+    fn truncated_objects() {
+        assert_eq!(field(r#"{"a": "complete", "b": "cut off her"#, "b"), "cut off her");
+        assert_eq!(field(r#"{"a": "x","#, "a"), "x");
+
+        let map = extract_json_object(r#"{"a": ["x", "y""#).unwrap();
+        assert_eq!(map["a"], serde_json::json!(["x", "y"]));
+    }
+
+    #[test]
+    fn smart_quotes_raw_newlines_and_stray_quotes() {
+        assert_eq!(field("{\u{201C}a\u{201D}: \u{201C}b\u{201D}}", "a"), "b");
+        assert_eq!(field("{\"a\": \"line1\nline2\"}", "a"), "line1\nline2");
+        assert_eq!(field(r#"{"a": "he said "hi" ok"}"#, "a"), "he said \"hi\" ok");
+    }
+
+    #[test]
+    fn no_object() {
+        assert!(extract_json_object("no json here").is_err());
+        assert!(extract_json_object("[1, 2]").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Summaries of single commits, issues and discussions kept between runs, so a report can be
+//! built from what earlier reports and ingests already summarized instead of fetching and
+//! summarizing the whole window again, and past activity can be looked up by person, type and
+//! date.
+//!
+//! Items are stored per repo and per day of their activity, under `memory:{owner_repo}:{date}`,
+//! which keeps each store value small and lets a window read only the days it covers. The repo's
+//! own summary is kept as a `Meta` memory next to them.
+
+use crate::github_data_fetchers::{ GitMemory, MemoryType, ReportWindow };
+use crate::platform::{ store_get, store_set };
+use chrono::{ DateTime, Duration, NaiveDate, Utc };
+use serde::{ Deserialize, Serialize };
+
+/// A report from memory was asked for a repo nothing has been stored for yet.
+#[derive(Debug)]
+pub struct NothingStored(pub String);
+
+impl std::fmt::Display for NothingStored {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Nothing stored for {}, run the daily ingest for it first", self.0)
+    }
+}
+
+impl std::error::Error for NothingStored {}
+
+/// What is stored for one repo and day.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryDay {
+    pub memories: Vec<GitMemory>,
+    /// `#number title url` of the issues seen closed.
+    #[serde(default)]
+    pub closed_issues: Vec<String>,
+}
+
+impl MemoryDay {
+    /// Adds the memories, replacing the ones for the same item and person.
+    fn merge(&mut self, memories: Vec<GitMemory>, closed_issues: Vec<String>) {
+        for memory in memories {
+            self.memories.retain(|m| !same_item(m, &memory));
+            self.memories.push(memory);
+        }
+        for line in closed_issues {
+            if !self.closed_issues.contains(&line) {
+                self.closed_issues.push(line);
+            }
+        }
+    }
+}
+
+// an issue is summarized once per participant, so the person is part of what identifies it
+fn same_item(a: &GitMemory, b: &GitMemory) -> bool {
+    a.memory_type == b.memory_type && a.source_url == b.source_url && a.name == b.name
+}
+
+fn repo_key(owner_repo: &str) -> String {
+    owner_repo.to_lowercase()
+}
+
+fn day_key(owner_repo: &str, day: NaiveDate) -> String {
+    format!("memory:{}:{}", repo_key(owner_repo), day)
+}
+
+fn meta_key(owner_repo: &str) -> String {
+    format!("memory_meta:{}", repo_key(owner_repo))
+}
+
+fn checkpoint_key(owner_repo: &str) -> String {
+    format!("ingest_checkpoint:{}", repo_key(owner_repo))
+}
+
+pub fn load_day(owner_repo: &str, day: NaiveDate) -> MemoryDay {
+    store_get(&day_key(owner_repo, day))
+        .and_then(|value| serde_json::from_value::<MemoryDay>(value).ok())
+        .unwrap_or_default()
+}
+
+fn save_day(owner_repo: &str, day: NaiveDate, memory_day: &MemoryDay) {
+    match serde_json::to_value(memory_day) {
+        Ok(value) => store_set(&day_key(owner_repo, day), value),
+        Err(_e) => log::error!("Failed to store the memories of {} for {}: {}", owner_repo, day, _e),
+    }
+}
+
+/// Stores the memories under the day of their `date`, `fallback` for those without one. Closed
+/// issues go under the day of `fallback`.
+pub fn remember(
+    owner_repo: &str,
+    memories: Vec<GitMemory>,
+    closed_issues: Vec<String>,
+    fallback: DateTime<Utc>
+) {
+    let mut by_day = Vec::<(NaiveDate, Vec<GitMemory>)>::new();
+    for memory in memories {
+        let day = memory.date.unwrap_or(fallback).date_naive();
+        match by_day.iter_mut().find(|(d, _)| *d == day) {
+            Some((_, day_memories)) => day_memories.push(memory),
+            None => by_day.push((day, vec![memory])),
+        }
+    }
+
+    let closed_day = fallback.date_naive();
+    if !closed_issues.is_empty() && !by_day.iter().any(|(d, _)| *d == closed_day) {
+        by_day.push((closed_day, Vec::new()));
+    }
+
+    let mut closed_issues = Some(closed_issues);
+    for (day, day_memories) in by_day {
+        let mut stored = load_day(owner_repo, day);
+        let closed = match day == closed_day {
+            true => closed_issues.take().unwrap_or_default(),
+            false => Vec::new(),
+        };
+        stored.merge(day_memories, closed);
+        save_day(owner_repo, day, &stored);
+    }
+}
+
+/// Everything stored for the days `window` touches, minus items dated outside it. An item
+/// summarized on several days comes back once, as its latest summary.
+pub fn recall(owner_repo: &str, window: &ReportWindow) -> MemoryDay {
+    let mut recalled = MemoryDay::default();
+    let mut day = window.since.date_naive();
+    let last_day = window.until.date_naive();
+    while day <= last_day {
+        let stored = load_day(owner_repo, day);
+        let memories = stored.memories
+            .into_iter()
+            .filter(|m| m.date.map_or(true, |date| window.contains(date)))
+            .collect::<Vec<GitMemory>>();
+        recalled.merge(memories, stored.closed_issues);
+        day = day + Duration::days(1);
+    }
+    recalled
+}
+
+/// The memories of `owner_repo` in `window`, newest first, narrowed to one person (by login, in
+/// any case) and one type when those are given.
+pub fn query_memories(
+    owner_repo: &str,
+    window: &ReportWindow,
+    user: Option<&str>,
+    memory_type: Option<MemoryType>
+) -> Vec<GitMemory> {
+    let mut memories = recall(owner_repo, window)
+        .memories.into_iter()
+        .filter(|m| user.map_or(true, |u| m.name.eq_ignore_ascii_case(u)))
+        .filter(|m| memory_type.map_or(true, |t| m.memory_type == t))
+        .collect::<Vec<GitMemory>>();
+    memories.sort_by(|a, b| b.date.cmp(&a.date));
+    memories
+}
+
+/// The stored summary of the repo itself.
+pub fn project_memory(owner_repo: &str) -> Option<GitMemory> {
+    store_get(&meta_key(owner_repo)).and_then(|value| serde_json::from_value::<GitMemory>(value).ok())
+}
+
+pub fn remember_project(owner_repo: &str, summary: &str) {
+    let memory = GitMemory {
+        memory_type: MemoryType::Meta,
+        name: owner_repo.to_string(),
+        tag_line: String::new(),
+        source_url: String::new(),
+        payload: summary.to_string(),
+        date: Some(Utc::now()),
+    };
+    if let Ok(value) = serde_json::to_value(&memory) {
+        store_set(&meta_key(owner_repo), value);
+    }
+}
+
+/// Where the last ingest of the repo ended.
+pub fn checkpoint(owner_repo: &str) -> Option<DateTime<Utc>> {
+    store_get(&checkpoint_key(owner_repo)).and_then(|value|
+        serde_json::from_value::<DateTime<Utc>>(value).ok()
+    )
+}
+
+pub fn set_checkpoint(owner_repo: &str, at: DateTime<Utc>) {
+    if let Ok(value) = serde_json::to_value(at) {
+        store_set(&checkpoint_key(owner_repo), value);
+    }
+}
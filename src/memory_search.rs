@@ -0,0 +1,141 @@
+//! Semantic search over stored memories, for questions such as "who worked on the auth refactor
+//! last quarter?". Each memory's title and summary is embedded once and the vector kept next to
+//! the memories of its day, under `memory_index:{owner_repo}:{date}`. A search embeds whatever in
+//! its window is not indexed yet, or was summarized again since, before ranking.
+
+use crate::embeddings::{ cosine_similarity, stable_hash, EmbeddingBackend };
+use crate::github_data_fetchers::{ GitMemory, MemoryType, ReportWindow };
+use crate::memory::load_day;
+use crate::platform::{ store_get, store_set };
+use chrono::{ Duration, NaiveDate };
+use serde::{ Deserialize, Serialize };
+use std::collections::{ HashMap, HashSet };
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct DayIndex {
+    /// The `EmbeddingBackend::id` the vectors came from.
+    backend: String,
+    entries: HashMap<String, IndexEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    /// Of the embedded text, to notice a memory summarized again.
+    text_hash: u64,
+    vector: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    /// Cosine similarity to the query, 1 being the closest.
+    pub score: f32,
+    pub memory: GitMemory,
+}
+
+fn index_key(owner_repo: &str, day: NaiveDate) -> String {
+    format!("memory_index:{}:{}", owner_repo.to_lowercase(), day)
+}
+
+fn memory_id(memory: &GitMemory) -> String {
+    format!("{:?}|{}|{}", memory.memory_type, memory.source_url, memory.name)
+}
+
+fn memory_text(memory: &GitMemory) -> String {
+    format!("{} by {}\n{}", memory.tag_line.trim(), memory.name, memory.payload.trim())
+}
+
+fn load_index(owner_repo: &str, day: NaiveDate, backend: &str) -> DayIndex {
+    store_get(&index_key(owner_repo, day))
+        .and_then(|value| serde_json::from_value::<DayIndex>(value).ok())
+        .filter(|index| index.backend == backend)
+        .unwrap_or_else(|| DayIndex {
+            backend: backend.to_string(),
+            entries: HashMap::new(),
+        })
+}
+
+/// The `limit` memories of `owner_repo` in `window` closest to `query`, closest first, narrowed to
+/// one person and one type when those are given.
+pub async fn search_memories(
+    owner_repo: &str,
+    query: &str,
+    window: &ReportWindow,
+    user: Option<&str>,
+    memory_type: Option<MemoryType>,
+    limit: usize,
+    backend: &EmbeddingBackend
+) -> anyhow::Result<Vec<SearchHit>> {
+    let backend_id = backend.id();
+    let mut days = Vec::<(NaiveDate, Vec<GitMemory>, DayIndex)>::new();
+    let mut day = window.since.date_naive();
+    while day <= window.until.date_naive() {
+        let memories = load_day(owner_repo, day)
+            .memories.into_iter()
+            .filter(|m| m.memory_type != MemoryType::Meta)
+            .collect::<Vec<GitMemory>>();
+        if !memories.is_empty() {
+            days.push((day, memories, load_index(owner_repo, day, &backend_id)));
+        }
+        day = day + Duration::days(1);
+    }
+
+    // everything not embedded yet goes out in one go, with the query
+    let mut pending = Vec::<(usize, String, u64)>::new();
+    let mut texts = vec![query.to_string()];
+    for (i, (_, memories, index)) in days.iter().enumerate() {
+        for memory in memories {
+            let text = memory_text(memory);
+            let text_hash = stable_hash(&text);
+            let id = memory_id(memory);
+            if index.entries.get(&id).map_or(true, |entry| entry.text_hash != text_hash) {
+                pending.push((i, id, text_hash));
+                texts.push(text);
+            }
+        }
+    }
+    let mut vectors = backend.embed(&texts).await?.into_iter();
+    let query_vector = vectors.next().unwrap_or_default();
+
+    let mut changed = vec![false; days.len()];
+    for ((i, id, text_hash), vector) in pending.into_iter().zip(vectors) {
+        days[i].2.entries.insert(id, IndexEntry { text_hash, vector });
+        changed[i] = true;
+    }
+
+    let mut hits = Vec::<SearchHit>::new();
+    for (i, (day, memories, mut index)) in days.into_iter().enumerate() {
+        if changed[i] {
+            // entries of memories replaced under another id are dropped with the save
+            index.entries.retain(|id, _| memories.iter().any(|m| &memory_id(m) == id));
+            match serde_json::to_value(&index) {
+                Ok(value) => store_set(&index_key(owner_repo, day), value),
+                Err(_e) => {
+                    log::error!("Failed to store the index of {} for {}: {}", owner_repo, day, _e)
+                }
+            }
+        }
+
+        for memory in memories {
+            let in_scope =
+                memory.date.map_or(true, |date| window.contains(date)) &&
+                user.map_or(true, |u| memory.name.eq_ignore_ascii_case(u)) &&
+                memory_type.map_or(true, |t| memory.memory_type == t);
+            if !in_scope {
+                continue;
+            }
+            if let Some(entry) = index.entries.get(&memory_id(&memory)) {
+                hits.push(SearchHit {
+                    score: cosine_similarity(&query_vector, &entry.vector),
+                    memory,
+                });
+            }
+        }
+    }
+
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    // the same item can be stored on two days; only its best match counts
+    let mut seen = HashSet::<String>::new();
+    hits.retain(|hit| seen.insert(memory_id(&hit.memory)));
+    hits.truncate(limit);
+    Ok(hits)
+}
@@ -0,0 +1,318 @@
+//! The places where the report engine reaches out to the network or keeps state: the octocrab
+//! client, plain HTTP requests, email, page scraping and a small key-value store. With the `flows`
+//! feature they go through the flows.network wasi crates and store-flows; with `native` they use
+//! the regular octocrab and reqwest clients and a JSON file, so the engine can run from a
+//! terminal, a cron job or another service.
+
+use crate::credentials::GithubCredentials;
+use crate::github_host::GithubHost;
+
+#[cfg(feature = "flows")]
+pub use github_flows::octocrab;
+#[cfg(feature = "native")]
+pub use octocrab_native as octocrab;
+
+/// An octocrab client for the configured GitHub host, sending `token` when there is one.
+pub fn build_octo(token: Option<String>) -> anyhow::Result<octocrab::Octocrab> {
+    let host = GithubHost::current();
+    let mut builder = octocrab::Octocrab::builder();
+    if !host.is_github_com() {
+        // the trailing slash keeps `/api/v3` when relative routes are joined onto it
+        builder = builder.base_url(format!("{}/", host.api_url()))?;
+    }
+    if let Some(token) = token {
+        builder = builder.personal_token(token);
+    }
+    Ok(builder.build()?)
+}
+
+/// An octocrab client acting as `creds`. If the App token can't be had, the error is logged and
+/// the platform's default login is used. The platform login only reaches github.com, so another
+/// host gets `GITHUB_TOKEN` instead.
+#[cfg(feature = "flows")]
+pub async fn github_octo(creds: &GithubCredentials) -> anyhow::Result<octocrab::Octocrab> {
+    let default_octo = || github_flows::get_octo(&github_flows::GithubLogin::Default);
+    let on_github_com = GithubHost::current().is_github_com();
+    let token = match on_github_com {
+        true => creds.scoped_token().await,
+        false => creds.token().await,
+    };
+    let octocrab = match token {
+        Ok(None) if on_github_com => default_octo(),
+        Ok(token) =>
+            build_octo(token).unwrap_or_else(|_e| {
+                log::error!("Error building a GitHub client for the scoped token: {}", _e);
+                default_octo()
+            }),
+        Err(_e) => {
+            log::error!("Error getting a GitHub token: {}", _e);
+            default_octo()
+        }
+    };
+    Ok(octocrab)
+}
+
+/// An octocrab client acting as `creds`. If the App token can't be had, the error is logged and
+/// `GITHUB_TOKEN` is used. A client that can't be built, say for a malformed `GITHUB_API_URL`,
+/// is an error.
+#[cfg(feature = "native")]
+pub async fn github_octo(creds: &GithubCredentials) -> anyhow::Result<octocrab::Octocrab> {
+    let token = match creds.token().await {
+        Ok(token) => token,
+        Err(_e) => {
+            log::error!("Error getting a GitHub token: {}", _e);
+            std::env::var("GITHUB_TOKEN").ok()
+        }
+    };
+    build_octo(token).map_err(|_e| anyhow::anyhow!("Failed to build the GitHub client: {}", _e))
+}
+
+/// Sends a GET request and returns the status code with the raw body.
+#[cfg(feature = "flows")]
+pub async fn http_get(url: &str, headers: &[(&str, String)]) -> anyhow::Result<(u16, Vec<u8>)> {
+    use http_req::{ request::Method, request::Request, uri::Uri };
+    let uri = Uri::try_from(url)?;
+    let mut writer = Vec::new();
+
+    let mut request = Request::new(&uri);
+    request.method(Method::GET).header("CONNECTION", "close");
+    for (name, value) in headers {
+        request.header(name, value);
+    }
+
+    match request.send(&mut writer) {
+        Ok(res) => Ok((u16::from(res.status_code()), writer)),
+        Err(_e) => Err(anyhow::anyhow!(_e)),
+    }
+}
+
+/// Sends a GET request and returns the status code with the raw body.
+#[cfg(feature = "native")]
+pub async fn http_get(url: &str, headers: &[(&str, String)]) -> anyhow::Result<(u16, Vec<u8>)> {
+    let client = reqwest::Client::new();
+    let mut request = client.get(url);
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+
+    let res = request.send().await?;
+    let status = res.status().as_u16();
+    Ok((status, res.bytes().await?.to_vec()))
+}
+
+/// Sends a POST request and returns the status code with the raw body.
+#[cfg(feature = "flows")]
+pub async fn http_post(
+    url: &str,
+    headers: &[(&str, String)],
+    body: &[u8]
+) -> anyhow::Result<(u16, Vec<u8>)> {
+    use http_req::{ request::Method, request::Request, uri::Uri };
+    let uri = Uri::try_from(url)?;
+    let mut writer = Vec::new();
+
+    let mut request = Request::new(&uri);
+    request
+        .method(Method::POST)
+        .header("CONNECTION", "close")
+        .header("Content-Length", &body.len())
+        .body(body);
+    for (name, value) in headers {
+        request.header(name, value);
+    }
+
+    match request.send(&mut writer) {
+        Ok(res) => Ok((u16::from(res.status_code()), writer)),
+        Err(_e) => Err(anyhow::anyhow!(_e)),
+    }
+}
+
+/// Sends a POST request and returns the status code with the raw body.
+#[cfg(feature = "native")]
+pub async fn http_post(
+    url: &str,
+    headers: &[(&str, String)],
+    body: &[u8]
+) -> anyhow::Result<(u16, Vec<u8>)> {
+    let client = reqwest::Client::new();
+    let mut request = client.post(url).body(body.to_vec());
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+
+    let res = request.send().await?;
+    let status = res.status().as_u16();
+    Ok((status, res.bytes().await?.to_vec()))
+}
+
+/// Sends a plain-text email. flows.network offers no SMTP client, so this is native only.
+#[cfg(feature = "flows")]
+pub async fn send_email(_to: &[String], _subject: &str, _body: &str) -> anyhow::Result<()> {
+    Err(anyhow::anyhow!("Email delivery needs the native build; use a webhook sink on flows.network"))
+}
+
+/// Sends a plain-text email through `SMTP_HOST`. `SMTP_SECURITY` is `starttls` (the default,
+/// port 587), `tls` (port 465) or `none`, the last one for a local test server; `SMTP_PORT`,
+/// `SMTP_USERNAME`, `SMTP_PASSWORD` and `SMTP_FROM` complete the settings.
+#[cfg(feature = "native")]
+pub async fn send_email(to: &[String], subject: &str, body: &str) -> anyhow::Result<()> {
+    use lettre::message::{ header::ContentType, Mailbox };
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{ AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor };
+
+    let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+    let host = var("SMTP_HOST").ok_or_else(|| anyhow::anyhow!("SMTP_HOST is not set"))?;
+    let from = var("SMTP_FROM").ok_or_else(|| anyhow::anyhow!("SMTP_FROM is not set"))?;
+    if to.is_empty() {
+        return Err(anyhow::anyhow!("The email sink has no recipients"));
+    }
+
+    let mut message = Message::builder().from(from.parse::<Mailbox>()?).subject(subject);
+    for recipient in to {
+        message = message.to(recipient.parse::<Mailbox>()?);
+    }
+    let message = message.header(ContentType::TEXT_PLAIN).body(body.to_string())?;
+
+    let security = var("SMTP_SECURITY").unwrap_or_else(|| String::from("starttls"));
+    let mut builder = match security.as_str() {
+        "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&host)?,
+        "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host),
+        _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)?,
+    };
+    if let Some(port) = var("SMTP_PORT") {
+        builder = builder.port(port.parse::<u16>()?);
+    }
+    if let (Some(username), Some(password)) = (var("SMTP_USERNAME"), var("SMTP_PASSWORD")) {
+        builder = builder.credentials(Credentials::new(username, password));
+    }
+
+    builder.build().send(message).await?;
+    Ok(())
+}
+
+/// Reads a value the deployment saved earlier, from store-flows.
+#[cfg(feature = "flows")]
+pub fn store_get(key: &str) -> Option<serde_json::Value> {
+    store_flows::get(key)
+}
+
+/// Saves a value under `key`, replacing what was there.
+#[cfg(feature = "flows")]
+pub fn store_set(key: &str, value: serde_json::Value) {
+    store_flows::set(key, value, None);
+}
+
+#[cfg(feature = "flows")]
+pub fn store_del(key: &str) {
+    store_flows::del(key);
+}
+
+// natively the store is one JSON object in the file named by GH_ANALYZER_STORE. It is parsed
+// once and kept for the life of the process; the file is read again only when its modification
+// time changes, as when a cron run of the CLI wrote to it
+#[cfg(feature = "native")]
+fn with_store<T>(
+    update: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>) -> (T, bool)
+) -> T {
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    struct Cached {
+        path: String,
+        modified: Option<SystemTime>,
+        map: serde_json::Map<String, serde_json::Value>,
+    }
+    static STORE: Mutex<Option<Cached>> = Mutex::new(None);
+    let mut cache = STORE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let path = std::env::var("GH_ANALYZER_STORE").unwrap_or_else(|_| String::from("gh-analyzer-store.json"));
+    let modified_at = |path: &str| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let modified = modified_at(&path);
+    if !matches!(&*cache, Some(c) if c.path == path && c.modified == modified) {
+        *cache = None;
+    }
+    let cached = cache.get_or_insert_with(|| Cached {
+        path: path.clone(),
+        modified,
+        map: std::fs
+            ::read_to_string(&path)
+            .ok()
+            .and_then(|raw| serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&raw).ok())
+            .unwrap_or_default(),
+    });
+
+    let (out, changed) = update(&mut cached.map);
+    if changed {
+        let written = serde_json
+            ::to_string_pretty(&cached.map)
+            .map_err(anyhow::Error::from)
+            .and_then(|raw| std::fs::write(&path, raw).map_err(anyhow::Error::from));
+        match written {
+            Ok(()) => {
+                cached.modified = modified_at(&path);
+            }
+            Err(_e) => log::error!("Error writing the store file {}: {}", path, _e),
+        }
+    }
+    out
+}
+
+/// Reads a value the deployment saved earlier, from the `GH_ANALYZER_STORE` file.
+#[cfg(feature = "native")]
+pub fn store_get(key: &str) -> Option<serde_json::Value> {
+    with_store(|map| (map.get(key).cloned(), false))
+}
+
+/// Saves a value under `key`, replacing what was there.
+#[cfg(feature = "native")]
+pub fn store_set(key: &str, value: serde_json::Value) {
+    with_store(|map| {
+        map.insert(key.to_string(), value);
+        ((), true)
+    })
+}
+
+#[cfg(feature = "native")]
+pub fn store_del(key: &str) {
+    with_store(|map| ((), map.remove(key).is_some()))
+}
+
+/// Fetches a web page and returns its visible text.
+#[cfg(feature = "flows")]
+pub async fn get_page_text(url: &str) -> anyhow::Result<String> {
+    web_scraper_flows::get_page_text(url).await.map_err(|_e| anyhow::anyhow!(_e))
+}
+
+/// Fetches a web page and returns its visible text.
+#[cfg(feature = "native")]
+pub async fn get_page_text(url: &str) -> anyhow::Result<String> {
+    let (status, body) = http_get(url, &[("User-Agent", String::from("github-analyzer"))]).await?;
+    if !(200..300).contains(&status) {
+        return Err(anyhow::anyhow!("http error {} fetching {}", status, url));
+    }
+    Ok(html_to_text(&String::from_utf8_lossy(&body)))
+}
+
+// drops tags, scripts and styles, and collapses whitespace; good enough to feed a summarizer
+#[cfg(feature = "native")]
+fn html_to_text(html: &str) -> String {
+    let without_code = regex::Regex
+        ::new(r"(?is)<(script|style|noscript|svg)\b.*?</(script|style|noscript|svg)>")
+        .map(|re| re.replace_all(html, " ").to_string())
+        .unwrap_or_else(|_| html.to_string());
+    let without_tags = regex::Regex
+        ::new(r"(?s)<[^>]*>")
+        .map(|re| re.replace_all(&without_code, " ").to_string())
+        .unwrap_or(without_code);
+
+    without_tags
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
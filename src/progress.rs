@@ -0,0 +1,111 @@
+//! How far a running report has got, for callers polling a background job.
+
+use serde::{ Deserialize, Serialize };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProgressSnapshot {
+    /// What the report is doing, such as `summarizing commits`.
+    pub phase: String,
+    /// Commits and issues fetched so far.
+    pub fetched: usize,
+    /// Of those, the ones the model has summarized.
+    pub summarized: usize,
+    /// Fetched items still waiting for a summary.
+    pub remaining: usize,
+    /// Of the fetched ones, those whose summary failed and was left out. They count as done.
+    #[serde(default)]
+    pub failed: usize,
+}
+
+type Listener = Box<dyn Fn(&ProgressSnapshot) + Send + Sync>;
+
+/// Counters the report bumps as it goes. A listener, if set, sees every change.
+#[derive(Default)]
+pub struct ReportProgress {
+    fetched: AtomicUsize,
+    summarized: AtomicUsize,
+    failed: AtomicUsize,
+    phase: Mutex<String>,
+    listener: Option<Listener>,
+}
+
+impl ReportProgress {
+    pub fn new() -> Self {
+        ReportProgress::default()
+    }
+
+    pub fn with_listener(listener: impl Fn(&ProgressSnapshot) + Send + Sync + 'static) -> Self {
+        ReportProgress {
+            listener: Some(Box::new(listener)),
+            ..ReportProgress::default()
+        }
+    }
+
+    pub fn phase(&self, phase: &str) {
+        if let Ok(mut current) = self.phase.lock() {
+            *current = phase.to_string();
+        }
+        self.notify();
+    }
+
+    pub fn fetched(&self, count: usize) {
+        self.fetched.fetch_add(count, Ordering::Relaxed);
+        self.notify();
+    }
+
+    pub fn summarized(&self, count: usize) {
+        self.summarized.fetch_add(count, Ordering::Relaxed);
+        self.notify();
+    }
+
+    pub fn failed(&self, count: usize) {
+        self.failed.fetch_add(count, Ordering::Relaxed);
+        self.notify();
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        let fetched = self.fetched.load(Ordering::Relaxed);
+        let summarized = self.summarized.load(Ordering::Relaxed);
+        let failed = self.failed.load(Ordering::Relaxed);
+        ProgressSnapshot {
+            phase: self.phase
+                .lock()
+                .map(|p| p.clone())
+                .unwrap_or_default(),
+            fetched,
+            summarized,
+            remaining: fetched.saturating_sub(summarized + failed),
+            failed,
+        }
+    }
+
+    fn notify(&self) {
+        if let Some(listener) = &self.listener {
+            listener(&self.snapshot());
+        }
+    }
+}
+
+impl std::fmt::Debug for ReportProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReportProgress").field("snapshot", &self.snapshot()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_items_count_as_done() {
+        let progress = ReportProgress::new();
+        progress.fetched(3);
+        progress.summarized(2);
+        progress.failed(1);
+        let snapshot = progress.snapshot();
+        assert_eq!(snapshot.remaining, 0);
+        assert_eq!(snapshot.failed, 1);
+    }
+}
@@ -0,0 +1,272 @@
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Named prompts used by the analyzers. Each one has a system and a user template, with
+/// `{{variable}}` placeholders filled in by `PromptRegistry::render`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PromptName {
+    Readme,
+    Commit,
+    Issue,
+    Discussion,
+    Correlation,
+    Scraper,
+    ExecutiveSummary,
+    ReleaseNotes,
+    Answer,
+}
+
+impl PromptName {
+    pub const ALL: [PromptName; 9] = [
+        PromptName::Readme,
+        PromptName::Commit,
+        PromptName::Issue,
+        PromptName::Discussion,
+        PromptName::Correlation,
+        PromptName::Scraper,
+        PromptName::ExecutiveSummary,
+        PromptName::ReleaseNotes,
+        PromptName::Answer,
+    ];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            PromptName::Readme => "readme",
+            PromptName::Commit => "commit",
+            PromptName::Issue => "issue",
+            PromptName::Discussion => "discussion",
+            PromptName::Correlation => "correlation",
+            PromptName::Scraper => "scraper",
+            PromptName::ExecutiveSummary => "executive_summary",
+            PromptName::ReleaseNotes => "release_notes",
+            PromptName::Answer => "answer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub system: String,
+    pub user: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PromptRegistry {
+    templates: HashMap<PromptName, PromptTemplate>,
+}
+
+impl PromptRegistry {
+    pub fn with_defaults() -> Self {
+        let templates = PromptName::ALL.iter()
+            .map(|name| (*name, default_template(*name)))
+            .collect::<HashMap<PromptName, PromptTemplate>>();
+
+        PromptRegistry { templates }
+    }
+
+    /// Builds the registry from the defaults, then applies overrides in increasing order of
+    /// precedence: the `prompt_templates` key in store-flows (on flows.network only), the JSON
+    /// file named by `PROMPT_TEMPLATES_FILE`, and the `PROMPT_<NAME>_SYSTEM` / `PROMPT_<NAME>_USER`
+    /// env vars.
+    pub fn load() -> Self {
+        let mut registry = PromptRegistry::with_defaults();
+
+        #[cfg(feature = "flows")]
+        if let Some(overrides) = store_flows::get("prompt_templates") {
+            registry.apply_overrides(&overrides);
+        }
+
+        if let Ok(path) = std::env::var("PROMPT_TEMPLATES_FILE") {
+            match std::fs::read_to_string(&path) {
+                Ok(raw) =>
+                    match serde_json::from_str::<Value>(&raw) {
+                        Ok(overrides) => registry.apply_overrides(&overrides),
+                        Err(e) => log::error!("Error parsing prompt templates file {}: {:?}", path, e),
+                    }
+                Err(e) => log::error!("Error reading prompt templates file {}: {:?}", path, e),
+            }
+        }
+
+        for name in PromptName::ALL.iter() {
+            let env_key = name.key().to_uppercase();
+            if let Some(template) = registry.templates.get_mut(name) {
+                if let Ok(system) = std::env::var(format!("PROMPT_{env_key}_SYSTEM")) {
+                    template.system = system;
+                }
+                if let Ok(user) = std::env::var(format!("PROMPT_{env_key}_USER")) {
+                    template.user = user;
+                }
+            }
+        }
+
+        registry
+    }
+
+    // overrides look like {"commit": {"system": "...", "user": "..."}}, either half may be left out
+    pub fn apply_overrides(&mut self, overrides: &Value) {
+        for name in PromptName::ALL.iter() {
+            let entry = match overrides.get(name.key()) {
+                Some(entry) => entry,
+                None => {
+                    continue;
+                }
+            };
+            if let Some(template) = self.templates.get_mut(name) {
+                if let Some(system) = entry.get("system").and_then(|v| v.as_str()) {
+                    template.system = system.to_string();
+                }
+                if let Some(user) = entry.get("user").and_then(|v| v.as_str()) {
+                    template.user = user.to_string();
+                }
+            }
+        }
+    }
+
+    pub fn template(&self, name: PromptName) -> PromptTemplate {
+        self.templates.get(&name).cloned().unwrap_or_else(|| default_template(name))
+    }
+
+    pub fn render(&self, name: PromptName, vars: &[(&str, &str)]) -> (String, String) {
+        let template = self.template(name);
+        (substitute(&template.system, vars), substitute(&template.user, vars))
+    }
+}
+
+pub fn prompt_registry() -> &'static PromptRegistry {
+    static REGISTRY: OnceLock<PromptRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(PromptRegistry::load)
+}
+
+pub fn render_prompt(name: PromptName, vars: &[(&str, &str)]) -> (String, String) {
+    prompt_registry().render(name, vars)
+}
+
+/// Replaces every `{{name}}` in a single pass, so placeholders that show up inside substituted
+/// values (e.g. in a commit message) are left alone. Unknown placeholders are kept verbatim.
+pub fn substitute(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        match after_open.find("}}") {
+            Some(end) => {
+                let key = after_open[..end].trim();
+                match vars.iter().find(|(k, _)| *k == key) {
+                    Some((_, value)) => out.push_str(value),
+                    None => out.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after_open[end + 2..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+fn default_template(name: PromptName) -> PromptTemplate {
+    let (system, user) = match name {
+        PromptName::Readme =>
+            (
+                "Your task is to objectively analyze a GitHub profile and the README of their project. Focus on extracting factual information about the features of the project, and its stated objectives. Avoid making judgments or inferring subjective value.",
+                "Based on the profile and README provided: {{content}}, extract a concise summary detailing this project's factual significance in its domain, their areas of expertise, and the main features and goals of the project. Ensure the insights are objective and under 110 tokens.",
+            ),
+        PromptName::Commit =>
+            (
+                "{{project_context}}Given a commit patch from user {{user_name}}, analyze its content. Focus on changes that substantively alter code or functionality. A good analysis prioritizes the commit message for clues on intent and refrains from overstating the impact of minor changes. Aim to provide a balanced, fact-based representation that distinguishes between major and minor contributions to the project. Keep your analysis concise.",
+                "Analyze the commit patch: {{patch}}, and its description: {{tag_line}}. Summarize the main changes, but only emphasize modifications that directly affect core functionality. A good summary is fact-based, derived primarily from the commit message, and avoids over-interpretation. It recognizes the difference between minor textual changes and substantial code adjustments. Conclude by evaluating the realistic impact of {{user_name}}'s contributions in this commit on the project. Limit the response to 110 tokens.",
+            ),
+        PromptName::Issue =>
+            (
+                "{{project_context}}Given the information that user '{{issue_creator_name}}' opened an issue titled '{{issue_title}}', your task is to deeply analyze the content of the issue posts. Distill the crux of the issue, the potential solutions suggested, and evaluate the significant contributions of the participants in resolving or progressing the discussion.",
+                "Analyze the GitHub issue content: {{issue_text}}. Provide a concise analysis touching upon: The central problem discussed in the issue. The main solutions proposed or agreed upon. Highlight the role and significance of '{{participants}}' in contributing towards the resolution or progression of the discussion. If the target person's contribution is negligible or non-existent, leave the corresponding summary blank. Format the analysis into a flat JSON structure with one level of depth where each key maps directly to a single string value. Use the following template, replacing 'contributor_name' with the actual contributor's name, and 'summary' with your analysis of their contributions or an empty string if their contribution is negligible:
+        {
+        \"contributor_name_1\": \"summary\",
+        \"contributor_name_2\": \"summary\"
+        }",
+            ),
+        PromptName::Discussion =>
+            (
+                "{{project_context}}Analyze the provided GitHub discussion. Identify the main topic, actions by participants, crucial viewpoints, solutions or consensus reached, and particularly highlight the contributions of specific individuals, especially '{{target}}'. Summarize without being verbose.",
+                "Analyze the content: {{discussion_text}}. Briefly summarize the central topic, participants' actions, primary viewpoints, and outcomes. Emphasize the role of '{{target}}' in driving the discussion or reaching a resolution. Aim for a succinct summary that is rich in analysis and under 192 tokens.",
+            ),
+        PromptName::Correlation =>
+            (
+                "You're a GitHub data analysis bot. You're tasked to analyze a GitHub contributor's activity data over the week to detect both key impactful contributions and connections between commits and issues. Highlight specific code changes, resolutions, and improvements. {{project_context}}",
+                r#"From {{commits_summary}}, {{issues_summary}}. Analyze the key technical contributions made by {{target_person}} this week and summarize the information into a flat JSON structure with just one level of depth. Each key in the JSON should map directly to a single string value describing the contribution or observation in a full sentence or a short paragraph without using nested objects or arrays. If no information is available for a point, provide an empty string as the value.
+Please ensure that the JSON output does not include any Markdown formatting, such as code block syntax ("```") or escaped characters (like "\\n" for new lines). The output should be plain JSON that can be parsed directly without any preprocessing.
+
+Your JSON response should use the following keys with appropriate string values:
+{{sections}}
+Ensure that the JSON is properly formatted, with correct escaping of special characters, and is ready to be parsed by a JSON parser that expects RFC8259-compliant JSON. Avoid adding any non-JSON content or formatting."#,
+            ),
+        PromptName::Scraper =>
+            (
+                "Your task is to examine the textual content from a GitHub repo page, emphasizing the Header, About, Release, Contributors, Languages, and README sections. This process should be carried out objectively, focusing on factual information extraction from each segment. Avoid making subjective judgments or inferences. The data should be presented systematically, corresponding to each section. Please note, the provided text will be in a flattened format.",
+                "I’ve obtained a flattened text from a GitHub repo page and require analysis of the following sections: 1) Header, with data on Fork, Star, Issues, Pull Request, etc.; 2) About, containing project description, keywords, number of stars, watchers, and forks; 3) Release, with details on the latest release and total releases; 4) Contributors, showing the number of contributors; 5) Languages, displaying the language composition in the project, and 6) README, which is usually a body of text describing the project, please summarize README when presenting result. Please extract and present data from these sections individually. Here is the text: {{page_text}}",
+            ),
+        PromptName::ExecutiveSummary =>
+            (
+                "You're a GitHub data analysis bot writing an executive summary for engineering leads. You're given a project description, the per-contributor activity reports of the week, and the issues closed during the week. Stay factual, rely only on the material provided, and keep the summary brief.",
+                "Project {{owner_repo}} is described as: {{project_summary}}. Per-contributor reports of the week: {{user_reports}}. Issues closed this week: {{closed_issues}}. Write a repo-level executive summary covering: 1) the main themes of the week's work; 2) the areas of the codebase that changed the most; 3) notable issues closed; 4) risks or open concerns worth the leads' attention. Present each point as a short paragraph, skip a point if the material says nothing about it, and limit the response to 300 tokens.",
+            ),
+        PromptName::ReleaseNotes =>
+            (
+                "You're a release manager drafting release notes for a GitHub project from the changes merged over a period. Write for the project's users: describe what changed for them, not how the code was edited. Stay factual and rely only on the material provided. {{project_context}}",
+                "Draft release notes for {{owner_repo}} covering the last {{n_days}} days. Commit summaries grouped by author, each group followed by the commit urls: {{commits}}. Issues closed in the same period: {{closed_issues}}. Group the changes under the headings Features, Fixes and Other changes, as Markdown bullet lists, leaving out empty headings. Mention closed issues next to the change that resolved them where it is clear which one did.",
+            ),
+        PromptName::Answer =>
+            (
+                "You answer questions about the recent activity of the GitHub project {{owner_repo}}, using only the numbered summaries of its commits, issues and discussions you are given. Cite the summaries you rely on by their number in square brackets, like [2]. If the summaries don't answer the question, say so instead of guessing. {{project_context}}",
+                "Summaries of the activity between {{since}} and {{until}}: {{sources}}. Earlier in this conversation: {{history}}. Question: {{question}}. Answer in a few sentences or a short Markdown list, citing the summaries by number.",
+            ),
+    };
+
+    PromptTemplate {
+        system: system.to_string(),
+        user: user.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitute_keeps_unknown_placeholders() {
+        assert_eq!(
+            substitute("Hi {{name}}, see {{missing}}.", &[("name", "Ada")]),
+            "Hi Ada, see {{missing}}."
+        );
+    }
+
+    #[test]
+    fn substitute_fills_every_occurrence() {
+        assert_eq!(
+            substitute("{{a}} and {{ a }} and {{b}}{{a}}", &[("a", "x"), ("b", "y")]),
+            "x and x and yx"
+        );
+    }
+
+    #[test]
+    fn substitute_leaves_placeholders_inside_values_alone() {
+        let vars = [("message", "fix {{patch}} handling {{"), ("patch", "PATCH")];
+        assert_eq!(
+            substitute("Commit: {{message}} / {{patch}}", &vars),
+            "Commit: fix {{patch}} handling {{ / PATCH"
+        );
+    }
+
+    #[test]
+    fn substitute_keeps_an_unclosed_brace_pair() {
+        assert_eq!(substitute("open {{ never closed", &[]), "open {{ never closed");
+    }
+}
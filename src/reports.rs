@@ -1,124 +1,545 @@
 use std::collections::HashMap;
+use crate::credentials::GithubCredentials;
 use crate::data_analyzers::*;
 use crate::github_data_fetchers::*;
+use crate::llm::{ LlmSession, ModelUse, PlannedCall };
+use crate::memory::{
+    checkpoint,
+    project_memory,
+    recall,
+    remember,
+    remember_project,
+    NothingStored,
+};
+use crate::sections::ReportSection;
+use crate::sinks::Deliverable;
+use crate::usage::UsageReport;
 use crate::utils::parse_summary_from_raw_json;
+use crate::platform::octocrab::models::{ issues::Issue, IssueState };
 use log;
+use serde::{ Deserialize, Serialize };
 // use octocrab_wasi::issues;
 // use store_flows::{del, get, set, Expire};
-use webhook_flows::send_response;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub body: String,
+    pub executive_summary: Option<String>,
+    pub contributors: Vec<ContributorReport>,
+    pub notes: Vec<String>,
+    pub metadata: ReportMetadata,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContributorReport {
+    pub user_name: String,
+    pub commits: Vec<String>,
+    pub issues: Vec<String>,
+    pub summary: Option<String>,
+}
+
+impl ContributorReport {
+    fn to_text(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "{} made {}:\n{}",
+                self.user_name,
+                plural(self.commits.len(), "commit"),
+                self.commits.join("\n")
+            )
+        ];
+        if !self.issues.is_empty() {
+            lines.push(
+                format!(
+                    "{} participated in {}:\n{}",
+                    self.user_name,
+                    plural(self.issues.len(), "issue"),
+                    self.issues.join("\n")
+                )
+            );
+        }
+        if let Some(summary) = &self.summary {
+            lines.push(summary.clone());
+        }
+        lines.join("\n")
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = format!("## {}\n\n", self.user_name);
+        if let Some(summary) = &self.summary {
+            out.push_str(&format!("{summary}\n\n"));
+        }
+        out.push_str(&format!("**{}**\n\n", plural(self.commits.len(), "commit")));
+        for url in &self.commits {
+            out.push_str(&format!("- {url}\n"));
+        }
+        if !self.issues.is_empty() {
+            out.push_str(&format!("\n**{}**\n\n", plural(self.issues.len(), "issue")));
+            for url in &self.issues {
+                out.push_str(&format!("- {url}\n"));
+            }
+        }
+        out
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {noun}"),
+        _ => format!("{count} {noun}s"),
+    }
+}
+
+impl Report {
+    /// Same layout as `body`, rebuilt from the structured parts.
+    pub fn to_text(&self) -> String {
+        let mut blocks = Vec::<String>::new();
+        if let Some(executive_summary) = &self.executive_summary {
+            blocks.push(format!("Executive summary:\n{executive_summary}"));
+        }
+        blocks.extend(self.contributors.iter().map(|c| c.to_text()));
+        blocks.extend(self.notes.iter().cloned());
+        blocks.join("\n\n")
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let meta = &self.metadata;
+        let mut out = format!("# Activity report for {}\n\n", meta.owner_repo);
+        out.push_str(&format!("_Last {} days", meta.n_days));
+        if let Some(user_name) = &meta.user_name {
+            out.push_str(&format!(", contributions by {user_name}"));
+        }
+        out.push_str("_\n\n");
+
+        if let Some(executive_summary) = &self.executive_summary {
+            out.push_str(&format!("## Executive summary\n\n{executive_summary}\n\n"));
+        }
+        for contributor in &self.contributors {
+            out.push_str(&contributor.to_markdown());
+            out.push_str("\n");
+        }
+        for note in &self.notes {
+            out.push_str(&format!("> {note}\n\n"));
+        }
+        out.push_str(
+            &format!(
+                "---\n{} LLM calls, est. ${:.4}\n",
+                meta.usage.total.calls,
+                meta.usage.total.estimated_cost_usd
+            )
+        );
+        out
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportMetadata {
+    pub owner_repo: String,
+    pub user_name: Option<String>,
+    pub n_days: u16,
+    pub window: ReportWindow,
+    pub models: Vec<ModelUse>,
+    pub usage: UsageReport,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<DryRunPlan>,
+}
+
+impl ReportMetadata {
+    fn collect(
+        owner_repo: &str,
+        user_name: Option<String>,
+        window: &ReportWindow,
+        fetched_items: Vec<FetchedItem>,
+        llm: &LlmSession
+    ) -> Self {
+        ReportMetadata {
+            owner_repo: owner_repo.to_string(),
+            user_name,
+            n_days: window.days(),
+            window: *window,
+            models: llm.models_used(),
+            usage: llm.usage(),
+            dry_run: llm
+                .is_dry_run()
+                .then(|| DryRunPlan::new(fetched_items, llm.planned_calls())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FetchedItem {
+    pub kind: String,
+    pub author: String,
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunPlan {
+    pub items: Vec<FetchedItem>,
+    pub calls: Vec<PlannedCall>,
+    pub prompt_tokens: u64,
+    pub max_completion_tokens: u64,
+    pub estimated_cost_usd: f64,
+}
+
+impl DryRunPlan {
+    pub fn new(items: Vec<FetchedItem>, calls: Vec<PlannedCall>) -> Self {
+        DryRunPlan {
+            prompt_tokens: calls
+                .iter()
+                .map(|c| c.prompt_tokens as u64)
+                .sum(),
+            max_completion_tokens: calls
+                .iter()
+                .map(|c| c.max_tokens as u64)
+                .sum(),
+            estimated_cost_usd: calls
+                .iter()
+                .map(|c| c.estimated_cost_usd)
+                .sum(),
+            items,
+            calls,
+        }
+    }
+}
+
+fn owner_of(owner_repo: &str) -> &str {
+    owner_repo.split('/').next().unwrap_or(owner_repo)
+}
+
+/// What a report covers, for callers that run reports without waiting on them: schedules and
+/// background jobs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReportTarget {
+    Repo {
+        owner_repo: String,
+        #[serde(default)]
+        username: Option<String>,
+        /// Build the report from the summaries stored by the daily ingest.
+        #[serde(default)]
+        from_memory: bool,
+    },
+    Org {
+        org: String,
+        #[serde(default = "default_max_repos")]
+        max_repos: usize,
+    },
+    ReleaseNotes {
+        owner_repo: String,
+    },
+}
+
+fn default_max_repos() -> usize {
+    5
+}
+
+/// Runs the report `target` names and renders it for delivery.
+pub async fn run_target(
+    target: &ReportTarget,
+    window: &ReportWindow,
+    creds: &GithubCredentials,
+    sections: &[ReportSection],
+    llm: &LlmSession
+) -> anyhow::Result<Deliverable> {
+    match target {
+        ReportTarget::Repo { owner_repo, username, from_memory: true } => {
+            let report = weekly_report_from_memory(
+                owner_repo,
+                username.clone(),
+                window,
+                sections,
+                llm
+            ).await?;
+            Ok(Deliverable::from_report(&report))
+        }
+        ReportTarget::Repo { owner_repo, username, from_memory: false } => {
+            let report = weekly_report(
+                owner_repo,
+                username.clone(),
+                window,
+                creds,
+                sections,
+                llm
+            ).await?;
+            Ok(Deliverable::from_report(&report))
+        }
+        ReportTarget::Org { org, max_repos } => {
+            let report = org_report(org, window, *max_repos, creds, sections, llm).await?;
+            Ok(Deliverable::from_org_report(&report))
+        }
+        ReportTarget::ReleaseNotes { owner_repo } => {
+            let notes = release_notes(owner_repo, window, creds, llm).await?;
+            Ok(Deliverable::from_release_notes(&notes))
+        }
+    }
+}
 
 pub async fn weekly_report(
     owner_repo: &str,
     user_name: Option<String>,
-    token: Option<String>
-) -> String {
-    let n_days = 7u16;
-
+    window: &ReportWindow,
+    creds: &GithubCredentials,
+    sections: &[ReportSection],
+    llm: &LlmSession
+) -> anyhow::Result<Report> {
+    // picks the GitHub App installation covering the repo, when the deployment is an App
+    let creds = &creds.for_owner(owner_of(owner_repo));
+    let progress = llm.progress();
     let contributors_set;
+    let project_summary;
 
-    match is_valid_owner_repo(owner_repo).await {
+    progress.phase("checking repo");
+    match is_valid_owner_repo(owner_repo, llm, creds).await {
         Err(_e) => {
-            send_response(
-                400,
-                vec![(String::from("content-type"), String::from("text/plain"))],
-                "You've entered invalid owner/repo, or the target is private. Please try again."
-                    .as_bytes()
-                    .to_vec()
-            );
-            std::process::exit(1);
+            return Err(_e);
         }
-        Ok((_, _, inner_set)) => {
+        Ok((_, summary, inner_set)) => {
+            project_summary = summary;
             contributors_set = inner_set;
         }
     }
 
+    let mut fetched_items = Vec::<FetchedItem>::new();
+    let mut memories = Vec::<GitMemory>::new();
     let mut commits_map = HashMap::<String, (String, String)>::new();
     'commits_block: {
-        match
-            get_commits_in_range_search(owner_repo, user_name.clone(), n_days, token.clone()).await
-        {
-            Some((count, commits_vec)) => {
-                match count {
-                    0 => {
-                        break 'commits_block;
-                    }
-                    _ => {}
-                }
-                let _ = process_commits(commits_vec, &mut commits_map, token.clone()).await;
-            }
-            None => log::error!("failed to get commits"),
+        progress.phase("fetching commits");
+        let (count, commits_vec) = get_commits_in_range_search(
+            owner_repo,
+            user_name.clone(),
+            window,
+            creds
+        ).await?;
+        if count == 0 {
+            break 'commits_block;
         }
+        progress.fetched(commits_vec.len());
+        progress.phase("summarizing commits");
+        fetched_items.extend(
+            commits_vec.iter().map(|commit| FetchedItem {
+                kind: String::from("commit"),
+                author: commit.name.clone(),
+                title: commit.tag_line.lines().next().unwrap_or_default().to_string(),
+                url: commit.source_url.clone(),
+            })
+        );
+        let commit_memories = process_commits(
+            commits_vec,
+            &project_summary,
+            llm,
+            creds
+        ).await;
+        commits_map = group_by_user(&commit_memories);
+        memories.extend(commit_memories);
     }
 
     let mut issues_map = HashMap::<String, (String, String)>::new();
+    let mut closed_issues = Vec::<String>::new();
 
     'issues_block: {
-        match get_issues_in_range(owner_repo, user_name.clone(), n_days, token.clone()).await {
-            Some((count, issue_vec)) => {
-                match count {
-                    0 => {
-                        break 'issues_block;
-                    }
-                    _ => {}
-                }
-                issues_map = match
-                    process_issues(
-                        issue_vec,
-                        user_name.clone(),
-                        contributors_set,
-                        token.clone()
-                    ).await
-                {
-                    Ok(map) => map,
-                    Err(_e) => HashMap::<String, (String, String)>::new(),
-                };
-            }
-            None => log::error!("failed to get issues"),
+        if llm.budget_exceeded() {
+            break 'issues_block;
+        }
+        progress.phase("fetching issues");
+        let (count, issue_vec) = get_issues_in_range(
+            owner_repo,
+            user_name.clone(),
+            window,
+            creds
+        ).await?;
+        if count == 0 {
+            break 'issues_block;
         }
+        progress.fetched(issue_vec.len());
+        progress.phase("summarizing issues");
+        fetched_items.extend(
+            issue_vec.iter().map(|issue| FetchedItem {
+                kind: String::from("issue"),
+                author: issue.user.login.clone(),
+                title: issue.title.clone(),
+                url: issue.html_url.to_string(),
+            })
+        );
+        closed_issues = closed_issue_lines(&issue_vec, window);
+        let issue_memories = process_issues(
+            issue_vec,
+            user_name.clone(),
+            contributors_set,
+            &project_summary,
+            llm,
+            creds
+        ).await.unwrap_or_default();
+        issues_map = group_by_user(&issue_memories);
+        memories.extend(issue_memories);
     }
 
-    let mut report = Vec::<String>::new();
+    // kept for later reports and queries; a dry run has no summaries to keep
+    if !llm.is_dry_run() {
+        remember_project(owner_repo, &project_summary);
+        remember(owner_repo, memories, closed_issues.clone(), window.until);
+    }
+
+    Ok(
+        assemble_report(
+            owner_repo,
+            user_name,
+            window,
+            &project_summary,
+            commits_map,
+            issues_map,
+            closed_issues,
+            fetched_items,
+            Vec::new(),
+            sections,
+            llm
+        ).await
+    )
+}
+
+/// The weekly report over summaries stored by earlier ingests: nothing is fetched from GitHub and
+/// only the correlation and executive summary calls are made. Discussion summaries are added to
+/// the issue summaries of their author.
+pub async fn weekly_report_from_memory(
+    owner_repo: &str,
+    user_name: Option<String>,
+    window: &ReportWindow,
+    sections: &[ReportSection],
+    llm: &LlmSession
+) -> anyhow::Result<Report> {
+    let project_summary = match project_memory(owner_repo) {
+        Some(meta) => meta.payload,
+        None => {
+            return Err(NothingStored(owner_repo.to_string()).into());
+        }
+    };
+
+    llm.progress().phase("recalling stored activity");
+    let recalled = recall(owner_repo, window);
+    let wanted = |memory: &GitMemory| {
+        user_name.as_ref().map_or(true, |u| u.eq_ignore_ascii_case(&memory.name))
+    };
+
+    let mine = recalled.memories
+        .into_iter()
+        .filter(|m| wanted(m))
+        .collect::<Vec<GitMemory>>();
+    let of_type = |memory_type: MemoryType| {
+        mine.iter()
+            .filter(|m| m.memory_type == memory_type)
+            .cloned()
+            .collect::<Vec<GitMemory>>()
+    };
+    let (commits, issues, discussions) = (
+        of_type(MemoryType::Commit),
+        of_type(MemoryType::Issue),
+        of_type(MemoryType::Discussion),
+    );
+
+    let fetched_items = [("commit", &commits), ("issue", &issues), ("discussion", &discussions)]
+        .into_iter()
+        .flat_map(|(kind, memories)| {
+            memories.iter().map(move |memory| FetchedItem {
+                kind: kind.to_string(),
+                author: memory.name.clone(),
+                title: memory.tag_line.lines().next().unwrap_or_default().to_string(),
+                url: memory.source_url.clone(),
+            })
+        })
+        .collect::<Vec<FetchedItem>>();
+
+    let commits_map = group_by_user(&commits);
+    let mut issues_map = group_by_user(&issues);
+    // given to the model with the issues, but not listed as one of the person's issues
+    for discussion in &discussions {
+        let entry = issues_map.entry(discussion.name.clone()).or_default();
+        entry.1 = format!("{}\n{}", entry.1, discussion.payload).trim().to_string();
+    }
+    llm.progress().fetched(fetched_items.len());
+    llm.progress().summarized(fetched_items.len());
+
+    let mut notes = Vec::<String>::new();
+    match checkpoint(owner_repo) {
+        Some(at) if at < window.until => {
+            notes.push(
+                format!("Stored activity only goes up to {}.", at.format("%Y-%m-%d %H:%M UTC"))
+            );
+        }
+        // the repo was ingested, but no run got through its whole window yet
+        None => notes.push(String::from("Stored activity may be incomplete.")),
+        _ => {}
+    }
+
+    Ok(
+        assemble_report(
+            owner_repo,
+            user_name,
+            window,
+            &project_summary,
+            commits_map,
+            issues_map,
+            recalled.closed_issues,
+            fetched_items,
+            notes,
+            sections,
+            llm
+        ).await
+    )
+}
+
+/// Correlates each contributor's commit and issue summaries into the report, adding the executive
+/// summary for whole-repo reports. `notes` are shown before the report's own.
+async fn assemble_report(
+    owner_repo: &str,
+    user_name: Option<String>,
+    window: &ReportWindow,
+    project_summary: &str,
+    commits_map: HashMap<String, (String, String)>,
+    issues_map: HashMap<String, (String, String)>,
+    closed_issues: Vec<String>,
+    fetched_items: Vec<FetchedItem>,
+    mut notes: Vec<String>,
+    sections: &[ReportSection],
+    llm: &LlmSession
+) -> Report {
+    let progress = llm.progress();
+    let mut contributors = Vec::<ContributorReport>::new();
+    let mut executive_summary = None;
 
     if commits_map.len() == 0 && issues_map.len() == 0 {
         match &user_name {
             Some(target_person) => {
-                report = vec![
+                notes.push(
                     format!(
                         "No useful data found for {}, you may try alternative means to find out more about {}",
                         target_person,
                         target_person
                     )
-                ];
+                );
             }
 
             None => {
-                report = vec!["No useful data found, nothing to report".to_string()];
+                notes.push("No useful data found, nothing to report".to_string());
             }
         }
     } else {
+        progress.phase("correlating");
         for (user_name, (commits_str, commits_summaries)) in commits_map {
-            let mut one_user_report = Vec::<String>::new();
-
-            let commits_count = commits_str.lines().count();
-
-            let commits_count_str = match commits_count {
-                1 => String::from("1 commit"),
-                _ => format!("{} commits", commits_count),
+            if llm.budget_exceeded() {
+                break;
+            }
+            let mut one_user_report = ContributorReport {
+                user_name: user_name.clone(),
+                commits: commits_str.lines().map(String::from).collect(),
+                issues: Vec::new(),
+                summary: None,
             };
-            one_user_report.push(format!("{user_name} made {commits_count_str}:\n{commits_str}"));
             // log::info!("found {commits_count} commits:\n{commits_str}");
 
             let issues_summaries = match issues_map.get(&user_name) {
                 Some(tup) => {
-                    let issues_str = tup.0.to_owned();
-                    let issues_count = issues_str.lines().count();
-                    let issues_count_str = match issues_count {
-                        1 => String::from("1 issue"),
-                        _ => format!("{} issues", issues_count),
-                    };
-                    one_user_report.push(
-                        format!("{user_name} participated in {issues_count_str}:\n{issues_str}")
-                    );
+                    one_user_report.issues = tup.0.lines().map(String::from).collect();
 
                     tup.1.to_owned()
                 }
@@ -128,7 +549,10 @@ pub async fn weekly_report(
                 correlate_commits_issues_sparse(
                     &commits_summaries,
                     &issues_summaries,
-                    &user_name
+                    &user_name,
+                    &project_summary,
+                    sections,
+                    llm
                 ).await
             {
                 None => {
@@ -137,10 +561,12 @@ pub async fn weekly_report(
                     log::info!("issue_summaries: {:?}", &issues_summaries);
                 }
                 Some(final_summary) => {
-                    match parse_summary_from_raw_json(&final_summary) {
+                    match parse_summary_from_raw_json(&final_summary, sections) {
                         Ok(clean_summary) => {
-                            one_user_report.push(clean_summary);
+                            one_user_report.summary = Some(clean_summary);
                         }
+                        // dry runs get no model output, only the prompts are of interest
+                        Err(_) if llm.is_dry_run() => {}
                         Err(_e) => {
                             log::error!(
                                 "Failed to parse summary for user: {}, summary: {:?}, {:?}",
@@ -154,9 +580,232 @@ pub async fn weekly_report(
                     }
                 }
             }
-            report.push(one_user_report.join("\n"));
+            contributors.push(one_user_report);
+        }
+
+        if user_name.is_none() && !contributors.is_empty() && !llm.budget_exceeded() {
+            progress.phase("executive summary");
+            let user_reports = contributors
+                .iter()
+                .map(|c| c.to_text())
+                .collect::<Vec<String>>()
+                .join("\n\n");
+            executive_summary = summarize_repo_activity(
+                owner_repo,
+                project_summary,
+                &user_reports,
+                &closed_issues.join("\n"),
+                llm
+            ).await;
+            if executive_summary.is_none() {
+                log::error!("Failed to generate executive summary for {}", owner_repo);
+            }
         }
     }
 
-    report.join("\n\n")
+    let usage = llm.usage();
+    log::info!(
+        "LLM usage for {}: {} calls, {} prompt + {} completion tokens, est. ${:.4}",
+        owner_repo,
+        usage.total.calls,
+        usage.total.prompt_tokens,
+        usage.total.completion_tokens,
+        usage.total.estimated_cost_usd
+    );
+    for (stage, stage_usage) in &usage.by_stage {
+        log::info!(
+            "  {}: {} calls, {} prompt + {} completion tokens, est. ${:.4}",
+            stage,
+            stage_usage.calls,
+            stage_usage.prompt_tokens,
+            stage_usage.completion_tokens,
+            stage_usage.estimated_cost_usd
+        );
+    }
+    if usage.budget_exceeded {
+        notes.push(
+            format!(
+                "Report stopped early: the LLM budget of ${:.2} was exceeded.",
+                usage.budget_usd.unwrap_or(0.0)
+            )
+        );
+    }
+
+    let mut report = Report {
+        body: String::new(),
+        executive_summary,
+        contributors,
+        notes,
+        metadata: ReportMetadata::collect(owner_repo, user_name, window, fetched_items, llm),
+    };
+    report.body = report.to_text();
+
+    report
+}
+
+/// The issues closed within `window`. The fetch covers issues updated in it, which includes
+/// issues closed long before that only saw a comment.
+pub fn closed_issue_lines(issues: &[Issue], window: &ReportWindow) -> Vec<String> {
+    issues
+        .iter()
+        .filter(|issue| matches!(issue.state, IssueState::Closed))
+        .filter(|issue| issue.closed_at.map_or(false, |at| window.contains(at)))
+        .map(|issue| format!("#{} {} {}", issue.number, issue.title, issue.html_url))
+        .collect::<Vec<String>>()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrgReport {
+    pub org: String,
+    pub n_days: u16,
+    pub window: ReportWindow,
+    pub repos: Vec<Report>,
+    /// Active repos left out, because of `max_repos`, an error or the LLM budget.
+    pub skipped: Vec<String>,
+    pub usage: UsageReport,
+}
+
+impl OrgReport {
+    pub fn to_text(&self) -> String {
+        let mut blocks = vec![format!("Activity in {} over the last {} days", self.org, self.n_days)];
+        blocks.extend(
+            self.repos
+                .iter()
+                .map(|report| format!("== {} ==\n{}", report.metadata.owner_repo, report.body))
+        );
+        if !self.skipped.is_empty() {
+            blocks.push(format!("Not covered: {}", self.skipped.join(", ")));
+        }
+        blocks.join("\n\n")
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut blocks = vec![format!("# {}: last {} days", self.org, self.n_days)];
+        blocks.extend(self.repos.iter().map(|report| report.to_markdown()));
+        if !self.skipped.is_empty() {
+            blocks.push(format!("> Not covered: {}", self.skipped.join(", ")));
+        }
+        blocks.join("\n\n---\n\n")
+    }
+}
+
+/// Runs `weekly_report` over the organization's most recently pushed repos, up to `max_repos`.
+pub async fn org_report(
+    org: &str,
+    window: &ReportWindow,
+    max_repos: usize,
+    creds: &GithubCredentials,
+    sections: &[ReportSection],
+    llm: &LlmSession
+) -> anyhow::Result<OrgReport> {
+    let creds = &creds.for_owner(org);
+    let active_repos = get_org_repos_active_since(org, window.days(), creds).await?;
+
+    let mut repos = Vec::<Report>::new();
+    let mut skipped = Vec::<String>::new();
+    for (i, owner_repo) in active_repos.into_iter().enumerate() {
+        if i >= max_repos || llm.budget_exceeded() {
+            skipped.push(owner_repo);
+            continue;
+        }
+        match weekly_report(&owner_repo, None, window, creds, sections, llm).await {
+            Ok(report) => repos.push(report),
+            Err(_e) => {
+                log::error!("Error reporting on {}: {}", owner_repo, _e);
+                skipped.push(owner_repo);
+            }
+        }
+    }
+
+    Ok(OrgReport {
+        org: org.to_string(),
+        n_days: window.days(),
+        window: *window,
+        repos,
+        skipped,
+        usage: llm.usage(),
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReleaseNotes {
+    pub body: String,
+    pub metadata: ReportMetadata,
+}
+
+/// Drafts user-facing release notes from the commits and closed issues of the window.
+pub async fn release_notes(
+    owner_repo: &str,
+    window: &ReportWindow,
+    creds: &GithubCredentials,
+    llm: &LlmSession
+) -> anyhow::Result<ReleaseNotes> {
+    let creds = &creds.for_owner(owner_of(owner_repo));
+    let project_summary = match is_valid_owner_repo(owner_repo, llm, creds).await {
+        Ok((_, summary, _)) => summary,
+        Err(_e) => {
+            return Err(_e);
+        }
+    };
+
+    let progress = llm.progress();
+    let mut fetched_items = Vec::<FetchedItem>::new();
+    let mut commits_map = HashMap::<String, (String, String)>::new();
+    progress.phase("fetching commits");
+    match get_commits_in_range_search(owner_repo, None, window, creds).await? {
+        (count, commits_vec) if count > 0 => {
+            progress.fetched(commits_vec.len());
+            progress.phase("summarizing commits");
+            fetched_items.extend(
+                commits_vec.iter().map(|commit| FetchedItem {
+                    kind: String::from("commit"),
+                    author: commit.name.clone(),
+                    title: commit.tag_line.lines().next().unwrap_or_default().to_string(),
+                    url: commit.source_url.clone(),
+                })
+            );
+            let commit_memories = process_commits(commits_vec, &project_summary, llm, creds).await;
+            commits_map = group_by_user(&commit_memories);
+            if !llm.is_dry_run() {
+                remember(owner_repo, commit_memories, Vec::new(), window.until);
+            }
+        }
+        _ => {}
+    }
+
+    progress.phase("fetching issues");
+    let (_, issue_vec) = get_issues_in_range(owner_repo, None, window, creds).await?;
+    let closed_issues = closed_issue_lines(&issue_vec, window);
+
+    if commits_map.is_empty() && closed_issues.is_empty() {
+        return Ok(ReleaseNotes {
+            body: format!(
+                "No changes found in {} over the last {} days",
+                owner_repo,
+                window.days()
+            ),
+            metadata: ReportMetadata::collect(owner_repo, None, window, fetched_items, llm),
+        });
+    }
+
+    let commits = commits_map
+        .iter()
+        .map(|(author, (urls, summaries))| format!("{author}:\n{summaries}\n{urls}"))
+        .collect::<Vec<String>>()
+        .join("\n\n");
+
+    progress.phase("drafting release notes");
+    let body = draft_release_notes(
+        owner_repo,
+        window.days(),
+        &project_summary,
+        &commits,
+        &closed_issues.join("\n"),
+        llm
+    ).await.ok_or_else(|| anyhow::anyhow!("Failed to draft release notes for {}", owner_repo))?;
+
+    Ok(ReleaseNotes {
+        body,
+        metadata: ReportMetadata::collect(owner_repo, None, window, fetched_items, llm),
+    })
 }
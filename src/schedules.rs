@@ -0,0 +1,258 @@
+//! Recurring reports. A schedule names what to report on, how often and where to deliver it; each
+//! tick (the flows.network cron trigger, `gh-analyzer run-schedules` or `/schedules/run`) runs the
+//! schedules that are due.
+//!
+//! The window of the last delivered run is stored per schedule and the next run starts exactly
+//! where it ended, so a failed or missed run is caught up by the next one instead of being lost,
+//! and no period is reported twice. A report that failed to fetch isn't delivered at all, and one
+//! that went out only in part is finished by the next run.
+//!
+//! Schedules come from the JSON array in the file named by `REPORT_SCHEDULES_FILE` when that is
+//! set, and from the `report_schedules` store key otherwise.
+
+use crate::credentials::GithubCredentials;
+use crate::github_data_fetchers::ReportWindow;
+use crate::llm::{ LlmConfig, LlmSession };
+use crate::platform::{ store_del, store_get, store_set };
+use crate::reports::{ run_target, ReportTarget };
+use crate::sections::sections_preset;
+use crate::sinks::{ Deliverable, ReportSink };
+use chrono::{ DateTime, Duration, Utc };
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use std::collections::HashMap;
+
+const SCHEDULES_KEY: &str = "report_schedules";
+
+fn default_every_days() -> u16 {
+    7
+}
+
+fn default_format() -> String {
+    String::from("markdown")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportSchedule {
+    pub id: String,
+    pub target: ReportTarget,
+    /// Days between runs: 7 for weekly, 1 for daily.
+    #[serde(default = "default_every_days")]
+    pub every_days: u16,
+    /// How far the first run looks back, `every_days` if unset. Later runs start where the
+    /// previous one ended.
+    #[serde(default)]
+    pub window_days: Option<u16>,
+    /// `text`, `markdown` or `json`, for sinks that don't have a format of their own.
+    #[serde(default = "default_format")]
+    pub format: String,
+    /// A sections preset name.
+    #[serde(default)]
+    pub sections: Option<String>,
+    pub sink: ReportSink,
+    /// LLM settings under the names of the webhook's query parameters, such as `model`.
+    #[serde(default)]
+    pub options: HashMap<String, Value>,
+}
+
+/// What is remembered about a schedule between runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleState {
+    pub last_window: ReportWindow,
+    pub delivered_at: DateTime<Utc>,
+}
+
+/// A report that went out only in part, as when Discord refused one of its messages. The next
+/// run finishes sending this one before anything else is generated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingDelivery {
+    pub window: ReportWindow,
+    pub deliverable: Deliverable,
+    /// Messages already delivered.
+    pub sent: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleRun {
+    pub id: String,
+    pub window: Option<ReportWindow>,
+    /// `delivered`, `not_due` or `failed`.
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn state_key(id: &str) -> String {
+    format!("schedule_state:{id}")
+}
+
+fn pending_key(id: &str) -> String {
+    format!("schedule_pending:{id}")
+}
+
+pub fn load_schedules() -> anyhow::Result<Vec<ReportSchedule>> {
+    match std::env::var("REPORT_SCHEDULES_FILE") {
+        Ok(path) => {
+            let raw = std::fs
+                ::read_to_string(&path)
+                .map_err(|_e| anyhow::anyhow!("Error reading {}: {}", path, _e))?;
+            Ok(serde_json::from_str::<Vec<ReportSchedule>>(&raw)?)
+        }
+        Err(_) =>
+            match store_get(SCHEDULES_KEY) {
+                Some(value) => Ok(serde_json::from_value::<Vec<ReportSchedule>>(value)?),
+                None => Ok(Vec::new()),
+            }
+    }
+}
+
+fn editable_schedules() -> anyhow::Result<Vec<ReportSchedule>> {
+    if std::env::var("REPORT_SCHEDULES_FILE").is_ok() {
+        return Err(
+            anyhow::anyhow!("Schedules are read from REPORT_SCHEDULES_FILE; edit that file instead")
+        );
+    }
+    load_schedules()
+}
+
+/// Adds the schedule, or replaces the one with the same id.
+pub fn save_schedule(schedule: ReportSchedule) -> anyhow::Result<()> {
+    if schedule.every_days == 0 {
+        return Err(anyhow::anyhow!("every_days must be at least 1"));
+    }
+    if let Some(preset) = &schedule.sections {
+        if sections_preset(preset).is_none() {
+            return Err(anyhow::anyhow!("Unknown sections preset: {preset}"));
+        }
+    }
+
+    let mut schedules = editable_schedules()?;
+    schedules.retain(|s| s.id != schedule.id);
+    schedules.push(schedule);
+    store_set(SCHEDULES_KEY, serde_json::to_value(&schedules)?);
+    Ok(())
+}
+
+/// Removes the schedule and its recorded window. Returns whether it existed.
+pub fn delete_schedule(id: &str) -> anyhow::Result<bool> {
+    let mut schedules = editable_schedules()?;
+    let before = schedules.len();
+    schedules.retain(|s| s.id != id);
+    store_set(SCHEDULES_KEY, serde_json::to_value(&schedules)?);
+    store_del(&state_key(id));
+    store_del(&pending_key(id));
+    Ok(schedules.len() < before)
+}
+
+pub fn schedule_state(id: &str) -> Option<ScheduleState> {
+    store_get(&state_key(id)).and_then(|value| serde_json::from_value::<ScheduleState>(value).ok())
+}
+
+pub fn pending_delivery(id: &str) -> Option<PendingDelivery> {
+    store_get(&pending_key(id)).and_then(|value|
+        serde_json::from_value::<PendingDelivery>(value).ok()
+    )
+}
+
+/// The window the next run should cover, or `None` while the schedule isn't due.
+pub fn next_window(
+    schedule: &ReportSchedule,
+    state: Option<&ScheduleState>,
+    now: DateTime<Utc>
+) -> Option<ReportWindow> {
+    match state {
+        None => {
+            let days = schedule.window_days.unwrap_or(schedule.every_days);
+            Some(ReportWindow::between(now - Duration::days(days as i64), now))
+        }
+        Some(state) => {
+            let due_at = state.last_window.until + Duration::days(schedule.every_days as i64);
+            // ticks drift by a few seconds; don't let that push a run back a whole tick
+            (now + Duration::minutes(30) >= due_at).then(||
+                ReportWindow::between(state.last_window.until, now)
+            )
+        }
+    }
+}
+
+/// Runs the schedule's report over `window` and delivers it, or finishes the delivery still
+/// pending for the schedule. The window is recorded only once delivery succeeded; a report that
+/// went out in part is kept so the next run sends only the rest.
+pub async fn run_schedule(schedule: &ReportSchedule, window: &ReportWindow) -> anyhow::Result<()> {
+    let mut pending = match pending_delivery(&schedule.id) {
+        Some(pending) => pending,
+        None => {
+            let preset = schedule.sections.as_deref().unwrap_or("default");
+            let sections = sections_preset(preset).ok_or_else(||
+                anyhow::anyhow!("Unknown sections preset in schedule {}", schedule.id)
+            )?;
+            let creds = GithubCredentials::deployment();
+            let llm = LlmSession::new(LlmConfig::load(&schedule.options));
+
+            PendingDelivery {
+                window: *window,
+                deliverable: run_target(&schedule.target, window, &creds, &sections, &llm).await?,
+                sent: 0,
+            }
+        }
+    };
+
+    let delivered = schedule.sink.deliver(
+        &pending.deliverable,
+        &schedule.format,
+        &mut pending.sent
+    ).await;
+    if let Err(_e) = delivered {
+        if pending.sent > 0 {
+            store_set(&pending_key(&schedule.id), serde_json::to_value(&pending)?);
+        }
+        return Err(_e);
+    }
+    store_del(&pending_key(&schedule.id));
+
+    let state = ScheduleState {
+        last_window: pending.window,
+        delivered_at: Utc::now(),
+    };
+    store_set(&state_key(&schedule.id), serde_json::to_value(&state)?);
+    Ok(())
+}
+
+/// Runs every schedule that is due at `now`, one after the other.
+pub async fn run_due_schedules(now: DateTime<Utc>) -> anyhow::Result<Vec<ScheduleRun>> {
+    let mut runs = Vec::<ScheduleRun>::new();
+    for schedule in load_schedules()? {
+        let state = schedule_state(&schedule.id);
+        let due = match pending_delivery(&schedule.id) {
+            Some(pending) => Some(pending.window),
+            None => next_window(&schedule, state.as_ref(), now),
+        };
+        let window = match due {
+            Some(window) => window,
+            None => {
+                runs.push(ScheduleRun {
+                    id: schedule.id.clone(),
+                    window: None,
+                    status: String::from("not_due"),
+                    error: None,
+                });
+                continue;
+            }
+        };
+
+        let (status, error) = match run_schedule(&schedule, &window).await {
+            Ok(()) => (String::from("delivered"), None),
+            Err(_e) => {
+                log::error!("Scheduled report {} failed: {}", schedule.id, _e);
+                (String::from("failed"), Some(_e.to_string()))
+            }
+        };
+        runs.push(ScheduleRun {
+            id: schedule.id.clone(),
+            window: Some(window),
+            status,
+            error,
+        });
+    }
+    Ok(runs)
+}
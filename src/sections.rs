@@ -0,0 +1,173 @@
+use serde::{ Deserialize, Serialize };
+use serde_json::{ Map, Value };
+
+/// One key of the per-contributor JSON the correlation step asks the model for. The prompt is
+/// generated from a list of these, and the model output is checked against the same list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportSection {
+    pub key: String,
+    pub instruction: String,
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl ReportSection {
+    pub fn new(key: &str, instruction: &str, required: bool) -> Self {
+        ReportSection {
+            key: key.to_string(),
+            instruction: instruction.to_string(),
+            required,
+        }
+    }
+}
+
+pub fn default_sections() -> Vec<ReportSection> {
+    vec![
+        ReportSection::new(
+            "impactful",
+            "Provide a single string value summarizing impactful contributions and their interconnections.",
+            true
+        ),
+        ReportSection::new(
+            "alignment",
+            "Provide a single string value explaining how the contributions align with the project's stated goals given above; if no goals were given, say so instead of guessing.",
+            true
+        ),
+        ReportSection::new(
+            "patterns",
+            "Provide a single string value identifying any recurring patterns or trends in the contributions.",
+            true
+        ),
+        ReportSection::new(
+            "synergy",
+            "Provide a single string value discussing the synergy between individual and collective advancement.",
+            true
+        ),
+        ReportSection::new(
+            "significance",
+            "Provide a single string value commenting on the significance of the contributions.",
+            true
+        )
+    ]
+}
+
+pub fn engineering_manager_sections() -> Vec<ReportSection> {
+    vec![
+        ReportSection::new(
+            "delivered",
+            "Provide a single string value summarizing the work the contributor shipped or moved forward this week.",
+            true
+        ),
+        ReportSection::new(
+            "risks",
+            "Provide a single string value describing risks visible in the activity, such as regressions, large unreviewed changes or fragile areas being touched.",
+            true
+        ),
+        ReportSection::new(
+            "blocked_work",
+            "Provide a single string value describing work that looks blocked or stalled, and on what.",
+            false
+        ),
+        ReportSection::new(
+            "review_load",
+            "Provide a single string value describing the review and triage work the contributor took on for others.",
+            false
+        )
+    ]
+}
+
+pub fn sections_preset(name: &str) -> Option<Vec<ReportSection>> {
+    match name {
+        "default" => Some(default_sections()),
+        "engineering_manager" => Some(engineering_manager_sections()),
+        _ => None,
+    }
+}
+
+pub fn check_sections(parsed: &Map<String, Value>, sections: &[ReportSection]) -> anyhow::Result<()> {
+    let missing = sections
+        .iter()
+        .filter(|section| section.required)
+        .filter(|section| !parsed.get(&section.key).map_or(false, |v| v.is_string()))
+        .map(|section| section.key.as_str())
+        .collect::<Vec<&str>>();
+
+    if !missing.is_empty() {
+        return Err(anyhow::anyhow!("Missing required sections: {}", missing.join(", ")));
+    }
+
+    Ok(())
+}
+
+/// Renders the `{"key": "instruction", ...}` block that gets spliced into the correlation prompt.
+pub fn sections_prompt(sections: &[ReportSection]) -> String {
+    let lines = sections
+        .iter()
+        .map(|section| {
+            let instruction = match section.required {
+                true => section.instruction.clone(),
+                false =>
+                    format!(
+                        "{} Leave it as an empty string if there is nothing to report.",
+                        section.instruction
+                    ),
+            };
+            format!(
+                "{}: {}",
+                Value::String(section.key.clone()),
+                Value::String(instruction)
+            )
+        })
+        .collect::<Vec<String>>();
+
+    format!("{{\n{}\n}}", lines.join(",\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parsed(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn check_sections_names_missing_required_keys() {
+        let sections = engineering_manager_sections();
+        let error = check_sections(&parsed(json!({"delivered": "shipped x"})), &sections)
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("risks"));
+        assert!(!error.contains("delivered"));
+        // optional sections may be left out
+        assert!(!error.contains("blocked_work"));
+    }
+
+    #[test]
+    fn check_sections_wants_strings() {
+        let sections = vec![ReportSection::new("risks", "Say what is risky.", true)];
+
+        assert!(check_sections(&parsed(json!({"risks": ["a", "b"]})), &sections).is_err());
+        assert!(check_sections(&parsed(json!({"risks": "none"})), &sections).is_ok());
+    }
+
+    #[test]
+    fn unknown_preset_is_none() {
+        assert!(sections_preset("no_such_preset").is_none());
+        assert_eq!(sections_preset("default").unwrap().len(), 5);
+    }
+
+    #[test]
+    fn sections_prompt_is_a_json_object_of_instructions() {
+        let sections = vec![
+            ReportSection::new("risks", "Say \"what\" is risky.", true),
+            ReportSection::new("blocked", "Say what is blocked.", false)
+        ];
+        let prompt = serde_json::from_str::<Value>(&sections_prompt(&sections)).unwrap();
+
+        assert_eq!(prompt["risks"], "Say \"what\" is risky.");
+        assert!(prompt["blocked"].as_str().unwrap().ends_with("nothing to report."));
+    }
+}
@@ -0,0 +1,522 @@
+//! Where finished reports are sent when nobody is waiting on the HTTP response, as with scheduled
+//! runs. Each sink renders the report in the format its destination reads best: plain text for
+//! Slack and email, Markdown for Discord and GitHub, and the schedule's format for a generic
+//! webhook.
+//!
+//! Every sink talks to a url it is given or to the configured GitHub host, so all of them can be
+//! pointed at a local stand-in server.
+
+use crate::credentials::GithubCredentials;
+use crate::graphql::GraphqlRequest;
+use crate::platform::{ github_octo, http_post, send_email };
+use crate::reports::{ OrgReport, ReleaseNotes, Report };
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+
+/// A finished report in every format a sink might want.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deliverable {
+    pub title: String,
+    pub text: String,
+    pub markdown: String,
+    pub json: Value,
+}
+
+impl Deliverable {
+    pub fn from_report(report: &Report) -> Self {
+        let meta = &report.metadata;
+        Deliverable {
+            title: format!("Activity report for {}: last {} days", meta.owner_repo, meta.n_days),
+            text: report.body.clone(),
+            markdown: report.to_markdown(),
+            json: serde_json::to_value(report).unwrap_or(Value::Null),
+        }
+    }
+
+    pub fn from_org_report(report: &OrgReport) -> Self {
+        Deliverable {
+            title: format!("Activity in {}: last {} days", report.org, report.n_days),
+            text: report.to_text(),
+            markdown: report.to_markdown(),
+            json: serde_json::to_value(report).unwrap_or(Value::Null),
+        }
+    }
+
+    pub fn from_release_notes(notes: &ReleaseNotes) -> Self {
+        Deliverable {
+            title: format!("Release notes for {}", notes.metadata.owner_repo),
+            text: notes.body.clone(),
+            markdown: notes.body.clone(),
+            json: serde_json::to_value(notes).unwrap_or(Value::Null),
+        }
+    }
+
+    /// The report as `text`, `markdown` or `json`, text being the fallback.
+    pub fn render(&self, format: &str) -> String {
+        match format {
+            "markdown" => self.markdown.clone(),
+            "json" => self.json.to_string(),
+            _ => self.text.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReportSink {
+    /// POSTs `{"title": ..., "format": ..., "body": ...}` to `url`.
+    Webhook {
+        url: String,
+    },
+    /// A Slack incoming webhook, or anything else taking `{"text": ...}`.
+    Slack {
+        webhook_url: String,
+    },
+    /// A Discord webhook. Long reports are split over several messages.
+    Discord {
+        webhook_url: String,
+    },
+    /// Sent through the SMTP server configured with the `SMTP_*` env vars.
+    Email {
+        to: Vec<String>,
+    },
+    /// Opens a new issue for every report.
+    GithubIssue {
+        owner_repo: String,
+        #[serde(default)]
+        labels: Vec<String>,
+    },
+    /// Comments on one standing issue or pull request.
+    GithubComment {
+        owner_repo: String,
+        issue_number: u64,
+    },
+    /// Starts a discussion in the category with this name or slug.
+    GithubDiscussion {
+        owner_repo: String,
+        category: String,
+    },
+}
+
+// Slack truncates messages at 40k characters, Discord refuses anything over 2000, GitHub bodies
+// stop at 65536
+const SLACK_LIMIT: usize = 39_000;
+const DISCORD_LIMIT: usize = 2_000;
+const GITHUB_BODY_LIMIT: usize = 65_000;
+
+impl ReportSink {
+    /// The format this sink sends, `requested` being the schedule's `format`.
+    pub fn format<'a>(&self, requested: &'a str) -> &'a str {
+        match self {
+            ReportSink::Webhook { .. } => requested,
+            ReportSink::Slack { .. } | ReportSink::Email { .. } => "text",
+            _ => "markdown",
+        }
+    }
+
+    /// Sends the report. `sent` counts the messages of it already delivered: those are skipped
+    /// and it is advanced after each one, so a delivery that failed partway can be finished
+    /// without repeating what went out. Only Discord sends more than one message.
+    pub async fn deliver(
+        &self,
+        deliverable: &Deliverable,
+        requested_format: &str,
+        sent: &mut usize
+    ) -> anyhow::Result<()> {
+        let format = self.format(requested_format);
+        let body = deliverable.render(format);
+
+        if let ReportSink::Discord { webhook_url } = self {
+            let text = format!("**{}**\n\n{}", deliverable.title, body);
+            for chunk in split_message(&text, DISCORD_LIMIT).into_iter().skip(*sent) {
+                post_json(webhook_url, &json!({"content": chunk})).await?;
+                *sent += 1;
+            }
+            return Ok(());
+        }
+        if *sent > 0 {
+            return Ok(());
+        }
+
+        let delivered = match self {
+            ReportSink::Webhook { url } => {
+                let payload = json!({
+                    "title": deliverable.title,
+                    "format": format,
+                    "body": body,
+                });
+                post_json(url, &payload).await
+            }
+            ReportSink::Slack { webhook_url } => {
+                let text = format!("*{}*\n\n{}", deliverable.title, body);
+                post_json(webhook_url, &json!({"text": truncate(&text, SLACK_LIMIT)})).await
+            }
+            // sent above, a message at a time
+            ReportSink::Discord { .. } => Ok(()),
+            ReportSink::Email { to } => send_email(to, &deliverable.title, &body).await,
+            ReportSink::GithubIssue { owner_repo, labels } => {
+                let payload = json!({
+                    "title": deliverable.title,
+                    "body": truncate(&body, GITHUB_BODY_LIMIT),
+                    "labels": labels,
+                });
+                github_post(owner_repo, &format!("repos/{owner_repo}/issues"), &payload).await
+            }
+            ReportSink::GithubComment { owner_repo, issue_number } => {
+                let text = format!("## {}\n\n{}", deliverable.title, body);
+                let payload = json!({"body": truncate(&text, GITHUB_BODY_LIMIT)});
+                let route = format!("repos/{owner_repo}/issues/{issue_number}/comments");
+                github_post(owner_repo, &route, &payload).await
+            }
+            ReportSink::GithubDiscussion { owner_repo, category } => {
+                create_discussion(
+                    owner_repo,
+                    category,
+                    &deliverable.title,
+                    &truncate(&body, GITHUB_BODY_LIMIT)
+                ).await
+            }
+        };
+        delivered?;
+        *sent = 1;
+        Ok(())
+    }
+}
+
+fn truncate(text: &str, limit: usize) -> String {
+    match text.char_indices().nth(limit) {
+        Some((end, _)) => format!("{}\n…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Splits at line breaks into pieces of at most `limit` characters; a single longer line is cut.
+fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::<String>::new();
+    let mut current = String::new();
+
+    for line in text.lines() {
+        let mut line = line.to_string();
+        while line.chars().count() > limit {
+            let cut = line
+                .char_indices()
+                .nth(limit)
+                .map(|(i, _)| i)
+                .unwrap_or(line.len());
+            if !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+            }
+            chunks.push(line[..cut].to_string());
+            line = line[cut..].to_string();
+        }
+
+        if !current.is_empty() && current.chars().count() + line.chars().count() + 1 > limit {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(&line);
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+async fn post_json(url: &str, payload: &Value) -> anyhow::Result<()> {
+    let headers = [
+        ("Content-Type", String::from("application/json")),
+        ("User-Agent", String::from("github-analyzer")),
+    ];
+    let (status, body) = http_post(url, &headers, payload.to_string().as_bytes()).await?;
+    match status {
+        200..=299 => Ok(()),
+        _ =>
+            Err(
+                anyhow::anyhow!(
+                    "Delivery failed with status {}: {}",
+                    status,
+                    String::from_utf8_lossy(&body).chars().take(200).collect::<String>()
+                )
+            ),
+    }
+}
+
+fn sink_credentials(owner_repo: &str) -> GithubCredentials {
+    let owner = owner_repo.split('/').next().unwrap_or(owner_repo);
+    GithubCredentials::deployment().for_owner(owner)
+}
+
+async fn github_post(owner_repo: &str, route: &str, payload: &Value) -> anyhow::Result<()> {
+    let octocrab = github_octo(&sink_credentials(owner_repo)).await?;
+    let _created: Value = octocrab
+        .post(route, Some(payload)).await
+        .map_err(|_e| anyhow::anyhow!("Failed to post the report to {}: {}", owner_repo, _e))?;
+    Ok(())
+}
+
+async fn create_discussion(
+    owner_repo: &str,
+    category: &str,
+    title: &str,
+    body: &str
+) -> anyhow::Result<()> {
+    #[derive(Deserialize)]
+    struct RepoRoot {
+        data: Option<RepoData>,
+    }
+    #[derive(Deserialize)]
+    struct RepoData {
+        repository: Option<Repository>,
+    }
+    #[derive(Deserialize)]
+    struct Repository {
+        id: String,
+        #[serde(rename = "discussionCategories")]
+        discussion_categories: Categories,
+    }
+    #[derive(Deserialize)]
+    struct Categories {
+        nodes: Vec<Category>,
+    }
+    #[derive(Deserialize)]
+    struct Category {
+        id: String,
+        name: String,
+        slug: String,
+    }
+
+    let (owner, name) = owner_repo
+        .split_once('/')
+        .ok_or_else(|| anyhow::anyhow!("owner_repo must look like owner/name: {owner_repo}"))?;
+    let creds = sink_credentials(owner_repo);
+
+    let query =
+        r#"
+        query ($owner: String!, $name: String!) {
+            repository(owner: $owner, name: $name) {
+                id
+                discussionCategories(first: 50) {
+                    nodes { id name slug }
+                }
+            }
+        }
+        "#;
+    let repo = GraphqlRequest::new(query)
+        .var("owner", owner)
+        .var("name", name)
+        .send::<RepoRoot>(&creds).await?
+        .data.and_then(|d| d.repository)
+        .ok_or_else(|| anyhow::anyhow!("{owner_repo} not found"))?;
+
+    let category_id = repo.discussion_categories.nodes
+        .into_iter()
+        .find(|c| c.name.eq_ignore_ascii_case(category) || c.slug == category)
+        .map(|c| c.id)
+        .ok_or_else(|| anyhow::anyhow!("{owner_repo} has no discussion category {category}"))?;
+
+    let mutation =
+        r#"
+        mutation ($repositoryId: ID!, $categoryId: ID!, $title: String!, $body: String!) {
+            createDiscussion(input: {repositoryId: $repositoryId, categoryId: $categoryId, title: $title, body: $body}) {
+                discussion { url }
+            }
+        }
+        "#;
+    let created: Value = GraphqlRequest::new(mutation)
+        .var("repositoryId", repo.id)
+        .var("categoryId", category_id)
+        .var("title", title)
+        .var("body", body)
+        .send(&creds).await?;
+
+    // a refused mutation still comes back with `data`, holding a null discussion
+    match created.pointer("/data/createDiscussion/discussion/url") {
+        Some(Value::String(_)) => Ok(()),
+        _ => Err(anyhow::anyhow!("GitHub did not create the discussion in {owner_repo}: {created}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_keeps_short_text_and_cuts_at_the_limit() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly10!", 10), "exactly10!");
+        assert_eq!(truncate("héllo wörld", 5), "héllo\n…");
+    }
+
+    #[test]
+    fn split_message_keeps_every_chunk_under_the_limit() {
+        let text = (0..300).map(|n| format!("line {n}")).collect::<Vec<String>>().join("\n");
+        let chunks = split_message(&text, 100);
+
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 100));
+        assert_eq!(chunks.join("\n"), text);
+    }
+
+    #[test]
+    fn split_message_cuts_a_line_longer_than_the_limit() {
+        let long_line = "é".repeat(250);
+        let text = format!("title\n{long_line}\nend");
+        let chunks = split_message(&text, 100);
+
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 100));
+        assert_eq!(chunks[0], "title");
+        assert_eq!(chunks[1], "é".repeat(100));
+        assert_eq!(chunks[3], format!("{}\nend", "é".repeat(50)));
+    }
+
+    #[test]
+    fn discord_limit_holds_for_a_long_report() {
+        let text = "a line of a long report\n".repeat(1_000);
+        assert!(split_message(&text, DISCORD_LIMIT).iter().all(|c| c.chars().count() <= 2_000));
+    }
+}
+
+/// Delivers to a stand-in server on localhost and checks what each sink sent.
+#[cfg(all(test, feature = "native"))]
+mod delivery_tests {
+    use super::*;
+    use std::io::{ BufRead, BufReader, Read, Write };
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// Answers the next `n` requests with `{}` and hands back each request line and JSON body.
+    fn listen(n: usize) -> (String, mpsc::Receiver<(String, Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(n) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let response = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                                Content-Length: 2\r\nConnection: close\r\n\r\n{}";
+                stream.write_all(response.as_bytes()).unwrap();
+                let body = serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null);
+                sender.send((request_line.trim().to_string(), body)).unwrap();
+            }
+        });
+        (base_url, receiver)
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio_native::runtime::Builder
+            ::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn deliverable(markdown: &str) -> Deliverable {
+        Deliverable {
+            title: String::from("Weekly"),
+            text: String::from("plain body"),
+            markdown: markdown.to_string(),
+            json: json!({"body": "json body"}),
+        }
+    }
+
+    #[test]
+    fn slack_sends_truncated_text() {
+        let (url, requests) = listen(1);
+        let sink = ReportSink::Slack { webhook_url: format!("{url}/hook") };
+        let mut sent = 0;
+        block_on(sink.deliver(&deliverable("# md"), "json", &mut sent)).unwrap();
+
+        let (request_line, body) = requests.recv().unwrap();
+        assert_eq!(request_line, "POST /hook HTTP/1.1");
+        assert_eq!(body, json!({"text": "*Weekly*\n\nplain body"}));
+        assert_eq!(sent, 1);
+    }
+
+    #[test]
+    fn webhook_sends_the_requested_format() {
+        let (url, requests) = listen(1);
+        let sink = ReportSink::Webhook { url: format!("{url}/report") };
+        let mut sent = 0;
+        block_on(sink.deliver(&deliverable("# md"), "json", &mut sent)).unwrap();
+
+        let (_, body) = requests.recv().unwrap();
+        assert_eq!(body["title"], "Weekly");
+        assert_eq!(body["format"], "json");
+        assert_eq!(body["body"], json!({"body": "json body"}).to_string());
+    }
+
+    #[test]
+    fn discord_sends_markdown_in_chunks_and_skips_those_sent() {
+        let markdown = "a line of the report\n".repeat(300);
+        let expected = split_message(&format!("**Weekly**\n\n{markdown}"), DISCORD_LIMIT);
+        assert!(expected.len() > 3);
+
+        let (url, requests) = listen(expected.len() - 2);
+        let sink = ReportSink::Discord { webhook_url: format!("{url}/discord") };
+        let mut sent = 2;
+        block_on(sink.deliver(&deliverable(&markdown), "text", &mut sent)).unwrap();
+
+        let contents = requests
+            .iter()
+            .take(expected.len() - 2)
+            .map(|(_, body)| body["content"].as_str().unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(contents, expected[2..].to_vec());
+        assert!(contents.iter().all(|c| c.chars().count() <= DISCORD_LIMIT));
+        assert_eq!(sent, expected.len());
+    }
+
+    #[test]
+    fn github_issue_and_comment_bodies() {
+        // the GitHub host is read once per process, so both GitHub sinks share one listener
+        let (url, requests) = listen(2);
+        std::env::set_var("GITHUB_API_URL", &url);
+
+        let issue = ReportSink::GithubIssue {
+            owner_repo: String::from("o/r"),
+            labels: vec![String::from("report")],
+        };
+        let comment = ReportSink::GithubComment {
+            owner_repo: String::from("o/r"),
+            issue_number: 7,
+        };
+        let long_markdown = "x".repeat(GITHUB_BODY_LIMIT + 10);
+        block_on(issue.deliver(&deliverable(&long_markdown), "text", &mut 0)).unwrap();
+        block_on(comment.deliver(&deliverable("# md"), "text", &mut 0)).unwrap();
+
+        let (request_line, body) = requests.recv().unwrap();
+        assert_eq!(request_line, "POST /repos/o/r/issues HTTP/1.1");
+        assert_eq!(body["title"], "Weekly");
+        assert_eq!(body["labels"], json!(["report"]));
+        assert_eq!(body["body"].as_str().unwrap().chars().count(), GITHUB_BODY_LIMIT + 2);
+
+        let (request_line, body) = requests.recv().unwrap();
+        assert_eq!(request_line, "POST /repos/o/r/issues/7/comments HTTP/1.1");
+        assert_eq!(body, json!({"body": "## Weekly\n\n# md"}));
+    }
+}
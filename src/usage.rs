@@ -0,0 +1,192 @@
+use crate::llm::Stage;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{ Mutex, OnceLock };
+use tiktoken_rs::CoreBPE;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TokenUsage {
+    pub calls: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub estimated_cost_usd: f64,
+}
+
+impl TokenUsage {
+    fn add(&mut self, prompt_tokens: u32, completion_tokens: u32, cost_usd: f64) {
+        self.calls += 1;
+        self.prompt_tokens += prompt_tokens as u64;
+        self.completion_tokens += completion_tokens as u64;
+        self.estimated_cost_usd += cost_usd;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageReport {
+    pub total: TokenUsage,
+    pub by_stage: BTreeMap<String, TokenUsage>,
+    pub by_model: BTreeMap<String, TokenUsage>,
+    pub budget_usd: Option<f64>,
+    pub budget_exceeded: bool,
+    /// Models used without a known price; their calls count as free in the totals.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unpriced_models: Vec<String>,
+    /// Set aside for calls in flight, so concurrent calls can't all pass the budget check
+    /// before any of them is recorded.
+    #[serde(skip)]
+    reserved_usd: f64,
+}
+
+/// Running token and cost totals for one report. Shared by all the concurrent LLM calls of the
+/// report, so it sits behind a mutex.
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    inner: Mutex<UsageReport>,
+}
+
+impl UsageLedger {
+    pub fn new(budget_usd: Option<f64>) -> Self {
+        UsageLedger {
+            inner: Mutex::new(UsageReport {
+                budget_usd,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn record(&self, stage: Stage, model: &str, prompt_tokens: u32, completion_tokens: u32) {
+        let cost_usd = estimate_cost_usd(model, prompt_tokens as u64, completion_tokens as u64);
+        if let Ok(mut usage) = self.inner.lock() {
+            usage.total.add(prompt_tokens, completion_tokens, cost_usd);
+            usage.by_stage
+                .entry(stage.key().to_string())
+                .or_default()
+                .add(prompt_tokens, completion_tokens, cost_usd);
+            usage.by_model
+                .entry(model.to_string())
+                .or_default()
+                .add(prompt_tokens, completion_tokens, cost_usd);
+            let unpriced = price_per_1k(model).is_none();
+            if unpriced && !usage.unpriced_models.iter().any(|m| m == model) {
+                usage.unpriced_models.push(model.to_string());
+            }
+            if let Some(budget_usd) = usage.budget_usd {
+                if usage.total.estimated_cost_usd >= budget_usd {
+                    usage.budget_exceeded = true;
+                }
+            }
+        }
+    }
+
+    /// Sets aside the most a call to `model` can cost, refusing it if that would take the spend
+    /// past the budget. The amount goes back with `release` once the call's usage is recorded.
+    pub fn reserve(
+        &self,
+        model: &str,
+        prompt_tokens: u64,
+        max_completion_tokens: u64
+    ) -> anyhow::Result<f64> {
+        let mut usage = self.inner
+            .lock()
+            .map_err(|_e| anyhow::anyhow!("The usage ledger is poisoned"))?;
+        let budget_usd = match usage.budget_usd {
+            Some(budget_usd) => budget_usd,
+            None => {
+                return Ok(0.0);
+            }
+        };
+        if price_per_1k(model).is_none() {
+            return Err(
+                anyhow::anyhow!(
+                    "No price is known for {}, so it can't run under a budget; add it to LLM_PRICES",
+                    model
+                )
+            );
+        }
+
+        let cost_usd = estimate_cost_usd(model, prompt_tokens, max_completion_tokens);
+        let committed_usd = usage.total.estimated_cost_usd + usage.reserved_usd;
+        if usage.budget_exceeded || committed_usd + cost_usd > budget_usd {
+            usage.budget_exceeded = true;
+            return Err(anyhow::anyhow!("LLM budget of ${:.2} exceeded", budget_usd));
+        }
+        usage.reserved_usd += cost_usd;
+        Ok(cost_usd)
+    }
+
+    pub fn release(&self, reserved_usd: f64) {
+        if let Ok(mut usage) = self.inner.lock() {
+            usage.reserved_usd = (usage.reserved_usd - reserved_usd).max(0.0);
+        }
+    }
+
+    pub fn budget_exceeded(&self) -> bool {
+        self.inner
+            .lock()
+            .map(|usage| usage.budget_exceeded)
+            .unwrap_or(false)
+    }
+
+    pub fn snapshot(&self) -> UsageReport {
+        self.inner
+            .lock()
+            .map(|usage| usage.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// USD per 1K prompt / completion tokens, if known. `LLM_PRICES` may hold a JSON object such as
+/// `{"my-model": [0.001, 0.002]}` to add or override entries.
+pub fn price_per_1k(model: &str) -> Option<(f64, f64)> {
+    if let Ok(raw) = std::env::var("LLM_PRICES") {
+        if let Ok(Value::Object(prices)) = serde_json::from_str::<Value>(&raw) {
+            if let Some([prompt, completion]) = prices
+                .get(model)
+                .and_then(|v| v.as_array())
+                .map(|v| v.iter().filter_map(|p| p.as_f64()).collect::<Vec<f64>>())
+                .as_deref()
+            {
+                return Some((*prompt, *completion));
+            }
+        }
+    }
+
+    // longer prefixes first, so "gpt-4o-mini" isn't priced as "gpt-4o" or "gpt-4"
+    let table: [(&str, f64, f64); 10] = [
+        ("gpt-3.5-turbo-16k", 0.003, 0.004),
+        ("gpt-3.5-turbo-1106", 0.001, 0.002),
+        ("gpt-3.5-turbo", 0.0005, 0.0015),
+        ("gpt-4o-mini", 0.00015, 0.0006),
+        ("gpt-4o", 0.005, 0.015),
+        ("gpt-4-turbo", 0.01, 0.03),
+        ("gpt-4-1106", 0.01, 0.03),
+        ("gpt-4-0125", 0.01, 0.03),
+        ("gpt-4-32k", 0.06, 0.12),
+        ("gpt-4", 0.03, 0.06),
+    ];
+
+    table
+        .iter()
+        .find(|(prefix, _, _)| model.starts_with(prefix))
+        .map(|(_, prompt, completion)| (*prompt, *completion))
+}
+
+/// The cost in USD, 0 for a model without a known price.
+pub fn estimate_cost_usd(model: &str, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+    let (prompt_price, completion_price) = price_per_1k(model).unwrap_or((0.0, 0.0));
+    ((prompt_tokens as f64) * prompt_price + (completion_tokens as f64) * completion_price) / 1000.0
+}
+
+/// The cl100k tokenizer, built once; building it parses the whole vocabulary.
+pub fn bpe() -> Option<&'static CoreBPE> {
+    static BPE: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    BPE.get_or_init(|| tiktoken_rs::cl100k_base().ok()).as_ref()
+}
+
+pub fn count_tokens(text: &str) -> usize {
+    match bpe() {
+        Some(bpe) => bpe.encode_ordinary(text).len(),
+        None => text.len() / 4,
+    }
+}
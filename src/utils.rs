@@ -1,17 +1,11 @@
-use openai_flows::{ chat::{ ChatModel, ChatOptions }, OpenAIFlows };
 use log;
 use serde_json::Value;
-use async_openai::{
-    types::{
-        // ChatCompletionFunctionsArgs, ChatCompletionRequestMessage,
-        ChatCompletionRequestSystemMessageArgs,
-        ChatCompletionRequestUserMessageArgs,
-        // ChatCompletionTool, ChatCompletionToolArgs, ChatCompletionToolType,
-        CreateChatCompletionRequestArgs,
-        // FinishReason,
-    },
-    Client,
-};
+use crate::llm_json::extract_json_object;
+use crate::credentials::{ redact_secrets, GithubCredentials };
+use crate::platform::http_get;
+use crate::sections::{ check_sections, ReportSection };
+use crate::usage::bpe;
+use chrono::Utc;
 
 pub fn squeeze_fit_remove_quoted(inp_str: &str, max_len: u16, split: f32) -> String {
     let mut body = String::new();
@@ -59,18 +53,44 @@ pub fn squeeze_fit_remove_quoted(inp_str: &str, max_len: u16, split: f32) -> Str
     final_text
 }
 
+pub fn project_context(project_summary: &str) -> String {
+    match project_summary.trim() {
+        "" => String::new(),
+        summary => format!("For context, the project's stated goals and main features are: {summary}. "),
+    }
+}
+
+/// A random id such as `job-18c2f3a9b10-9f3c0e2d4b5a6c7d`: the creation time in milliseconds,
+/// then 64 bits from the OS's secure random source. Ids name jobs and chats that only their holder
+/// should find.
+pub fn new_id(prefix: &str) -> String {
+    let mut random = [0u8; 8];
+    // an id anyone could guess would hand out other callers' jobs and chats
+    getrandom::getrandom(&mut random).expect("the OS must provide secure random bytes");
+    format!("{prefix}-{:x}-{:016x}", Utc::now().timestamp_millis(), u64::from_le_bytes(random))
+}
+
 pub fn squeeze_fit_post_texts(inp_str: &str, max_len: u16, split: f32) -> String {
-    let bpe = tiktoken_rs::cl100k_base().unwrap();
+    let bpe = match bpe() {
+        Some(bpe) => bpe,
+        None => {
+            return inp_str.to_string();
+        }
+    };
 
     let input_token_vec = bpe.encode_ordinary(inp_str);
     let input_len = input_token_vec.len();
-    if input_len < (max_len as usize) {
+    if input_len <= (max_len as usize) {
         return inp_str.to_string();
     }
-    let n_take_from_beginning = ((input_len as f32) * split).ceil() as usize;
-    let n_take_from_end = (max_len as usize) - n_take_from_beginning;
+    // `split` of the budget goes to the beginning and the rest to the end, neither past the input
+    let max_len = max_len as usize;
+    let n_take_from_beginning = (((max_len as f32) * split.clamp(0.0, 1.0)) as usize).min(input_len);
+    let n_take_from_end = (max_len - n_take_from_beginning.min(max_len)).min(
+        input_len - n_take_from_beginning
+    );
 
-    let mut concatenated_tokens = Vec::with_capacity(max_len as usize);
+    let mut concatenated_tokens = Vec::with_capacity(max_len);
     concatenated_tokens.extend_from_slice(&input_token_vec[..n_take_from_beginning]);
     concatenated_tokens.extend_from_slice(&input_token_vec[input_len - n_take_from_end..]);
 
@@ -79,326 +99,129 @@ pub fn squeeze_fit_post_texts(inp_str: &str, max_len: u16, split: f32) -> String
         .map_or("failed to decode tokens".to_string(), |s| s.to_string())
 }
 
-/* pub async fn chain_of_chat(
-    sys_prompt_1: &str,
-    usr_prompt_1: &str,
-    chat_id: &str,
-    gen_len_1: u16,
-    usr_prompt_2: &str,
-    gen_len_2: u16,
-    error_tag: &str
+pub fn parse_summary_from_raw_json(
+    input: &str,
+    sections: &[ReportSection]
 ) -> anyhow::Result<String> {
-    let client = Client::new();
+    let parsed = extract_json_object(input)?;
+    check_sections(&parsed, sections)?;
 
-    let mut messages = vec![
-        ChatCompletionRequestSystemMessageArgs::default()
-            .content(sys_prompt_1)
-            .build()
-            .expect("Failed to build system message")
-            .into(),
-        ChatCompletionRequestUserMessageArgs::default().content(usr_prompt_1).build()?.into()
-    ];
-    let request = CreateChatCompletionRequestArgs::default()
-        .max_tokens(gen_len_1)
-        .model(ChatModel::GPT35Turbo16K)
-        .messages(messages.clone())
-        .build()?;
-
-    let chat = client.chat().create(request).await?;
+    let mut output = String::new();
 
-    match chat.choices[0].message.clone().content {
-        Some(_) => {
-            // log::info!("{:?}", res);
-        }
-        None => {
-            return Err(anyhow::anyhow!(error_tag.to_string()));
+    for section in sections.iter() {
+        if let Some(value) = parsed.get(&section.key).and_then(|v| v.as_str()) {
+            if value.is_empty() {
+                continue;
+            }
+            if !output.is_empty() {
+                output.push_str(" ");
+            }
+            output.push_str(value);
         }
     }
 
-    messages.push(
-        ChatCompletionRequestUserMessageArgs::default().content(usr_prompt_2).build()?.into()
-    );
-
-    let request = CreateChatCompletionRequestArgs::default()
-        .max_tokens(gen_len_2)
-        .model(ChatModel::GPT35Turbo16K)
-        .messages(messages)
-        .build()?;
-
-    let chat = client.chat().create(request).await?;
+    Ok(output)
+}
+/// GitHub answered with an error status. Kept as its own type so callers can tell a missing or
+/// hidden resource (404) from a refused one (401/403) by downcasting.
+#[derive(Debug)]
+pub struct GithubHttpError {
+    pub status: u16,
+    pub message: String,
+}
 
-    match chat.choices[0].message.clone().content {
-        Some(res) => {
-            // log::info!("{:?}", res);
-            Ok(res)
-        }
-        None => {
-            return Err(anyhow::anyhow!(error_tag.to_string()));
-        }
+impl std::fmt::Display for GithubHttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Github http error {}: {}", self.status, self.message)
     }
-} */
-
-pub async fn chat_inner(
-    system_prompt: &str,
-    user_input: &str,
-    max_token: u16,
-    model: ChatModel
-) -> anyhow::Result<String> {
-    let openai = OpenAIFlows::new();
+}
 
-    let co = ChatOptions {
-        model: model,
-        restart: true,
-        system_prompt: Some(system_prompt),
-        max_tokens: Some(max_token),
-        temperature: Some(0.7),
-        ..Default::default()
-    };
+impl std::error::Error for GithubHttpError {}
 
-    match openai.chat_completion("chat_id", user_input, &co).await {
-        Ok(res) => Ok(res.choice),
-        Err(_e) => Err(anyhow::anyhow!("OpenAI Error {:?}", _e)),
-    }
+pub async fn github_http_get(url: &str, creds: &GithubCredentials) -> anyhow::Result<Vec<u8>> {
+    github_http_get_as(url, "application/vnd.github+json", creds).await
 }
 
-pub async fn chat_inner_async(
-    system_prompt: &str,
-    user_input: &str,
-    max_token: u16,
-    model: &str
-) -> anyhow::Result<String> {
-    let client = Client::new();
-
-    let messages = vec![
-        ChatCompletionRequestSystemMessageArgs::default()
-            .content(system_prompt)
-            .build()
-            .expect("Failed to build system message")
-            .into(),
-        ChatCompletionRequestUserMessageArgs::default().content(user_input).build()?.into()
+/// A GET with an explicit `Accept` media type, such as `application/vnd.github.patch`.
+pub async fn github_http_get_as(
+    url: &str,
+    accept: &str,
+    creds: &GithubCredentials
+) -> anyhow::Result<Vec<u8>> {
+    let mut headers = vec![
+        ("User-Agent", String::from("flows-network connector")),
+        ("Accept", accept.to_string())
     ];
-    let request = CreateChatCompletionRequestArgs::default()
-        .max_tokens(max_token)
-        .model(model)
-        .messages(messages)
-        .build()?;
-
-    let chat = client.chat().create(request).await?;
-
-    // let check = chat.choices.get(0).clone().unwrap();
-    // send_message_to_channel("ik8", "general", format!("{:?}", check)).await;
-
-    match chat.choices[0].message.clone().content {
-        Some(res) => {
-            // log::info!("{:?}", chat.choices[0].message.clone());
-            Ok(res)
-        }
-        None => Err(anyhow::anyhow!("Failed to get reply from OpenAI")),
+    if let Some(authorization) = creds.authorization_header().await? {
+        headers.push(("Authorization", authorization));
     }
-}
 
-pub fn parse_summary_from_raw_json(input: &str) -> anyhow::Result<String> {
-    use regex::Regex;
-    let  parsed = match serde_json::from_str(input) {
-        Ok(v) => v,
-        Err(e) => {
-            log::error!("Error parsing JSON: {:?}", e);
-            // Attempt to extract fields using regex if JSON parsing fails
-            let mut values_map = std::collections::HashMap::new();
-            let keys = ["impactful", "alignment", "patterns", "synergy", "significance"];
-            for key in keys.iter() {
-                let regex_pattern = format!(r#""{}":\s*"([^"]*)""#, key);
-                let regex = Regex::new(&regex_pattern).map_err(|_|
-                    anyhow::Error::msg("Failed to compile regex pattern")
-                ).expect("Failed to compile regex pattern");
-                if let Some(captures) = regex.captures(input) {
-                    if let Some(value) = captures.get(1) {
-                        values_map.insert(*key, value.as_str().to_string());
-                    }
-                }
+    match http_get(url, &headers).await {
+        Ok((status, body)) => {
+            if !(200..300).contains(&status) {
+                log::error!("Github http error {:?} for {}", status, redact_secrets(url));
+                let message = serde_json
+                    ::from_slice::<Value>(&body)
+                    .ok()
+                    .and_then(|v| v.get("message").and_then(|m| m.as_str()).map(String::from))
+                    .unwrap_or_default();
+                return Err(GithubHttpError { status, message }.into());
             }
-
-            if values_map.len() != keys.len() {
-                return Err(anyhow::Error::msg("Failed to extract all fields from JSON"));
-            }
-
-            Value::Object(
-                values_map
-                    .into_iter()
-                    .map(|(k, v)| (k.to_string(), Value::String(v)))
-                    .collect()
-            )
+            Ok(body)
         }
-    };
-
-    let mut output = String::new();
-    let keys = ["impactful", "alignment", "patterns", "synergy", "significance"];
-
-    for key in keys.iter() {
-        if let Some(value) = parsed.get(*key) {
-            if value.is_string() {
-                if !output.is_empty() {
-                    output.push_str(" ");
-                }
-                output.push_str(value.as_str().unwrap());
-            }
+        Err(_e) => {
+            log::error!(
+                "Error getting response from Github: {}",
+                redact_secrets(&format!("{:?}", _e))
+            );
+            Err(_e)
         }
     }
-
-    Ok(output)
 }
-/* pub fn parse_summary_from_raw_json(input: &str) -> anyhow::Result<String> {
-    let parsed: Value = match serde_json::from_str(input) {
-        Ok(v) => v,
-        Err(_e) => {log::error!("Error parsing json: {:?}", _e); return Err(anyhow::anyhow!(_e))},
-    };
 
-    let mut output = String::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let keys = ["impactful", "alignment", "patterns", "synergy", "significance"];
-
-    for key in keys.iter() {
-        if let Some(value) = parsed.get(key) {
-            if value.is_string() {
-                if !output.is_empty() {
-                    output.push_str(" ");
-                }
-                output.push_str(value.as_str().unwrap());
-            }
-        }
+    fn long_text() -> String {
+        (0..3_000).map(|n| format!("word{n}")).collect::<Vec<String>>().join(" ")
     }
-    Ok(output)
-} */
 
-pub fn parse_issue_summary_from_json(input: &str) -> anyhow::Result<Vec<(String, String)>> {
-    let parsed: serde_json::Map<String, serde_json::Value> = serde_json::from_str(input)?;
-
-    let summaries = parsed
-        .iter()
-        .filter_map(|(key, value)| {
-            if let Some(summary_str) = value.as_str() {
-                Some((key.clone(), summary_str.to_owned()))
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<(String, String)>>(); // Collect into a Vec of tuples
-
-    Ok(summaries)
-}
-
-/* pub async fn github_http_post_gql(query: &str) -> anyhow::Result<Vec<u8>> {
-    use http_req::{request::Method, request::Request, uri::Uri};
-    let token = std::env::var("GITHUB_TOKEN").expect("github_token is required");
-    let base_url = "https://api.github.com/graphql";
-    let base_url = Uri::try_from(base_url).unwrap();
-    let mut writer = Vec::new();
-
-    let query = serde_json::json!({"query": query});
-    match Request::new(&base_url)
-        .method(Method::POST)
-        .header("User-Agent", "flows-network connector")
-        .header("Content-Type", "application/json")
-        .header("Authorization", &format!("Bearer {}", token))
-        .header("Content-Length", &query.to_string().len())
-        .body(&query.to_string().into_bytes())
-        .send(&mut writer)
-    {
-        Ok(res) => {
-            if !res.status_code().is_success() {
-                log::error!("Github http error {:?}", res.status_code());
-                return Err(anyhow::anyhow!("Github http error {:?}", res.status_code()));
-            };
-            Ok(writer)
-        }
-        Err(_e) => {
-            log::error!("Error getting response from Github: {:?}", _e);
-            Err(anyhow::anyhow!(_e))
-        }
+    #[test]
+    fn new_id_is_prefixed_and_unique() {
+        let first = new_id("job");
+        let second = new_id("job");
+        assert!(first.starts_with("job-"));
+        assert_eq!(first.rsplit('-').next().map(str::len), Some(16));
+        assert_ne!(first, second);
     }
-} */
 
-pub async fn github_http_get(url: &str) -> anyhow::Result<Vec<u8>> {
-    use http_req::{ request::Method, request::Request, uri::Uri };
-    let token = std::env::var("GITHUB_TOKEN").expect("github_token is required");
-    let mut writer = Vec::new();
-    let url = Uri::try_from(url).unwrap();
+    #[test]
+    fn squeeze_fit_post_texts_keeps_short_input() {
+        assert_eq!(squeeze_fit_post_texts("a short text", 100, 0.7), "a short text");
+    }
 
-    match
-        Request::new(&url)
-            .method(Method::GET)
-            .header("User-Agent", "flows-network connector")
-            .header("Content-Type", "application/json")
-            .header("Authorization", &format!("Bearer {}", token))
-            .header("CONNECTION", "close")
-            .send(&mut writer)
-    {
-        Ok(res) => {
-            if !res.status_code().is_success() {
-                log::error!("Github http error {:?}", res.status_code());
-                return Err(anyhow::anyhow!("Github http error {:?}", res.status_code()));
+    #[test]
+    fn squeeze_fit_post_texts_fits_long_input_for_any_split() {
+        let text = long_text();
+        let bpe = bpe().unwrap();
+        assert!(bpe.encode_ordinary(&text).len() > 1_500);
+
+        for split in [0.2, 0.7, 1.0] {
+            let squeezed = squeeze_fit_post_texts(&text, 1_500, split);
+            // decoding and encoding again can merge tokens at the seam, never add many
+            assert!(bpe.encode_ordinary(&squeezed).len() <= 1_502, "split {split}");
+            assert!(squeezed.starts_with("word0 word1"), "split {split}");
+            if split < 1.0 {
+                assert!(squeezed.ends_with("word2999"), "split {split}");
             }
-            Ok(writer)
-        }
-        Err(_e) => {
-            log::error!("Error getting response from Github: {:?}", _e);
-            Err(anyhow::anyhow!(_e))
         }
     }
-}
 
-pub async fn chain_of_chat(
-    sys_prompt_1: &str,
-    usr_prompt_1: &str,
-    chat_id: &str,
-    gen_len_1: u16,
-    usr_prompt_2: &str,
-    gen_len_2: u16,
-    error_tag: &str
-) -> anyhow::Result<String> {
-    let openai = OpenAIFlows::new();
-
-    let co_1 = ChatOptions {
-        model: ChatModel::GPT35Turbo16K,
-        restart: true,
-        system_prompt: Some(sys_prompt_1),
-        max_tokens: Some(gen_len_1),
-        temperature: Some(0.7),
-        ..Default::default()
-    };
-
-    match openai.chat_completion(chat_id, usr_prompt_1, &co_1).await {
-        Ok(res_1) => {
-            let sys_prompt_2 =
-                serde_json::json!([{"role": "system", "content": sys_prompt_1},
-    {"role": "user", "content": usr_prompt_1},
-    {"role": "assistant", "content": &res_1.choice}]).to_string();
-
-            let co_2 = ChatOptions {
-                model: ChatModel::GPT35Turbo16K,
-                restart: false,
-                system_prompt: Some(&sys_prompt_2),
-                max_tokens: Some(gen_len_2),
-                temperature: Some(0.7),
-                ..Default::default()
-            };
-            match openai.chat_completion(chat_id, usr_prompt_2, &co_2).await {
-                Ok(res_2) => {
-                    if res_2.choice.len() < 10 {
-                        log::error!(
-                            "{}, GPT generation went sideway: {:?}",
-                            error_tag,
-                            res_2.choice
-                        );
-                        return Err(anyhow::anyhow!(error_tag.to_string()));
-                    }
-                    return Ok(res_2.choice);
-                }
-                Err(_e) => log::error!("{}, Step 2 GPT generation error {:?}", error_tag, _e),
-            };
-        }
-        Err(_e) => log::error!("{}, Step 1 GPT generation error {:?}", error_tag, _e),
+    #[test]
+    fn squeeze_fit_post_texts_keeps_only_the_end_for_split_zero() {
+        let squeezed = squeeze_fit_post_texts(&long_text(), 500, 0.0);
+        assert!(squeezed.ends_with("word2999"));
+        assert!(!squeezed.starts_with("word0 "));
     }
-
-    Err(anyhow::anyhow!(error_tag.to_string()))
 }